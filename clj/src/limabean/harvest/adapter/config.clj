@@ -38,7 +38,8 @@
                 (fn [realizers]
                   (mapv #(-> %
                              (resolve-qualified-symbols :txn-fns ctx)
-                             (resolve-qualified-symbols :bal-fns ctx))
+                             (resolve-qualified-symbols :bal-fns ctx)
+                             (resolve-qualified-symbols :pos-fns ctx))
                     realizers))))))
 
 ;; from https://clojuredocs.org/clojure.core/merge-with
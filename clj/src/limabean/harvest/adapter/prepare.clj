@@ -64,7 +64,8 @@
 (defn- ingest
   "Ingest an import file once it has been classified.
 
-  Note that a single file ingests as a list of hulls, each a map with keys :hdr :txns."
+  Note that a single file ingests as a list of hulls, each a map with keys :hdr :txns,
+  and :positions for investment statements, which is empty if absent."
   [classified]
   (let [{:keys [ingester path]} classified
         cmd (substitute ingester :path path)
@@ -75,6 +76,7 @@
           ((fn [hulls]
              (mapv (fn [hull]
                      (-> hull
+                         (update :positions #(or % []))
                          (assoc :meta (:meta classified))
                          (update :hdr #(merge % (:hdr classified)))))
                hulls))))
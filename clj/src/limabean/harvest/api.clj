@@ -10,3 +10,9 @@
   (cond-> x
     (and (:date x) (jt/local-date? (:date x)))
       (assoc :date (jt/plus (:date x) (jt/days 1)))))
+
+(defn as-liability
+  "If there are positive units, negate them, as for the balance of a liability reported as the amount owed"
+  [x]
+  (cond-> x
+    (and (number? (:units x)) (pos? (:units x))) (update :units -)))
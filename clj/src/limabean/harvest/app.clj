@@ -43,13 +43,27 @@
                     (digest/resolve-accid-xf digest))
               txns)))
 
+(defn pos-from-prepared-ef
+  "Eduction to harvest balances from positions, if any, from a single prepared import file"
+  [config digest prepared]
+  (let [{:keys [hdr positions realizer]} prepared]
+    (eduction (comp (logging/wrap (correlation/xf)
+                                  {:id ::ingested-pos, :data {:hdr hdr}})
+                    (logging/wrap (realize/pos-xf realizer
+                                                  hdr
+                                                  {:config-path (:path config)})
+                                  {:id ::realized-pos})
+                    (digest/resolve-accid-xf digest))
+              positions)))
+
 (defn txns-and-bal-from-prepared-xf
-  "Return a transducer to harvest txns and balance from a single prepared import file"
+  "Return a transducer to harvest txns and balances from a single prepared import file"
   [config digest]
   (mapcat (fn [prepared]
             (eduction cat
                       [(txns-from-prepared-ef config digest prepared)
-                       (bal-from-prepared-ef config digest prepared)]))))
+                       (bal-from-prepared-ef config digest prepared)
+                       (pos-from-prepared-ef config digest prepared)]))))
 
 (defn harvest-txns
   "Eduction to harvest transaction from import paths"
@@ -5,7 +5,7 @@
    :classifiers [{:id :generic-ofx,
                   :hdr {:dialect "generic.ofx"},
                   :ingester ["hull-ofx" :path],
-                  :selector {:path-glob "**.{ofx,qfx}"}}],
+                  :selector {:path-glob "**.{ofx,qfx,qbo}"}}],
    :output {:columns {:comment 40, :units 75},
             :default {:acc {:assets "Assets:Unknown",
                             :expenses "Expenses:Unknown",
@@ -19,11 +19,38 @@
              :units {:key :balamt, :src :hdr, :type :decimal}},
        :bal-fns ['limabean.harvest.api/inc-date],
        :id :generic-ofx,
-       :selector {:dialect "generic.ofx"},
+       :selector {:dialect "generic.ofx", :msgset "bankmsgsrsv1"},
        :txn {:accid {:key :acctid, :src :hdr},
              :cur {:key :curdef, :src :hdr},
              :date {:fmt "yyyyMMdd", :key :dtposted, :src :txn, :type :date},
              :narration {:key :memo, :src :txn},
              :payee {:key :name, :src :txn},
              :txnid [{:key :acctid, :src :hdr} "." {:key :fitid, :src :txn}],
-             :units {:key :trnamt, :src :txn, :type :decimal}}}]})
+             :units {:key :trnamt, :src :txn, :type :decimal}}}
+      {:base :generic-ofx,
+       :id :generic-ofx-creditcard,
+       :selector {:dialect "generic.ofx", :msgset "creditcardmsgsrsv1"}}
+      {:bal {:accid {:key :acctid, :src :hdr},
+             :cur {:key :curdef, :src :hdr},
+             :date {:fmt "yyyyMMdd",
+                    :key :prinbal.dtasof,
+                    :src :hdr,
+                    :type :date},
+             :units {:key :prinbal.balamt, :src :hdr, :type :decimal}},
+       :bal-fns ['limabean.harvest.api/inc-date
+                 'limabean.harvest.api/as-liability],
+       :base :generic-ofx,
+       :id :generic-ofx-loan,
+       :selector {:dialect "generic.ofx", :msgset "loanmsgsrsv1"}}
+      {:bal {:accid {:key :acctid, :src :hdr},
+             :cur {:key :curdef, :src :hdr},
+             :date {:fmt "yyyyMMdd", :key :dtasof, :src :hdr, :type :date},
+             :units {:key :invbal.availcash, :src :hdr, :type :decimal}},
+       :base :generic-ofx,
+       :id :generic-ofx-investment,
+       :pos {:accid {:key :acctid, :src :hdr},
+             :cur {:key :ticker, :src :txn},
+             :date {:fmt "yyyyMMdd", :key :dtasof, :src :hdr, :type :date},
+             :units {:key :units, :src :txn, :type :decimal}},
+       :pos-fns ['limabean.harvest.api/inc-date],
+       :selector {:dialect "generic.ofx", :msgset "invstmtmsgsrsv1"}}]})
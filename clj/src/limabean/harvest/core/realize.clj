@@ -52,26 +52,38 @@
 (defn- max-by-date [x1 x2] (if (jt/after? (:date x1) (:date x2)) x1 x2))
 
 (defn bal-xf
-  "Transducer to realize just the most recent balance, if any"
+  "Transducer to realize just the most recent balance, if any, and if the realizer has :bal"
   [realizer hdr ctx]
-  (fn [rf]
-    (let [state (volatile! nil)] ;; latest-bal, if any
-      (fn
-        ;; init
-        ([] (rf))
-        ;; completion
-        ([result]
-         (let [latest-bal @state
-               ;; emit latest-bal, if any
-               result' (if latest-bal (rf result latest-bal) result)]
-           (rf result')))
-        ;; step
-        ([result txn]
-         (let [prev-bal @state
-               txn-bal
-                 (realize-bal (:bal realizer) (:bal-fns realizer) hdr txn ctx)
-               latest-bal (if (and prev-bal txn-bal)
-                            (max-by-date txn-bal prev-bal)
-                            txn-bal)]
-           (vreset! state latest-bal))
-         result)))))
+  (if-not (:bal realizer)
+    (filter (constantly false))
+    (fn [rf]
+      (let [state (volatile! nil)] ;; latest-bal, if any
+        (fn
+          ;; init
+          ([] (rf))
+          ;; completion
+          ([result]
+           (let [latest-bal @state
+                 ;; emit latest-bal, if any
+                 result' (if latest-bal (rf result latest-bal) result)]
+             (rf result')))
+          ;; step
+          ([result txn]
+           (let [prev-bal @state
+                 txn-bal
+                   (realize-bal (:bal realizer) (:bal-fns realizer) hdr txn ctx)
+                 latest-bal (if (and prev-bal txn-bal)
+                              (max-by-date txn-bal prev-bal)
+                              txn-bal)]
+             (vreset! state latest-bal))
+           result))))))
+
+(defn pos-xf
+  "Transducer to realize positions, if the realizer has :pos, as balances per commodity.
+
+  The fields of each position are available as :src :txn."
+  [realizer hdr ctx]
+  (if-let [pos-realizer (:pos realizer)]
+    (map (fn [pos]
+           (realize-bal pos-realizer (:pos-fns realizer) hdr pos ctx)))
+    (filter (constantly false))))
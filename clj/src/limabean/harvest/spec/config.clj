@@ -37,12 +37,14 @@
 (s/def ::selector (s/map-of keyword? string?))
 (s/def ::bal ::field-map)
 (s/def ::bal-fns (s/coll-of symbol? :kind vector?))
+(s/def ::pos ::field-map)
+(s/def ::pos-fns (s/coll-of symbol? :kind vector?))
 (s/def ::txn ::field-map)
 (s/def ::txn-fns (s/coll-of symbol? :kind vector?))
 
 (s/def ::realizer
   (s/keys :req-un [::id ::selector]
-          :opt-un [::base ::bal ::bal-fns ::txn ::txn-fns]))
+          :opt-un [::base ::bal ::bal-fns ::pos ::pos-fns ::txn ::txn-fns]))
 
 (s/def ::realizers (s/coll-of ::realizer :kind vector?))
 
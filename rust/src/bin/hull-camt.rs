@@ -0,0 +1,40 @@
+use clap::Parser;
+use color_eyre::eyre::{Context, Result};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = "Hull an ISO 20022 camt.053 or camt.052 file for import into limabean-harvest", long_about = None)]
+struct Cli {
+    /// File to ingest
+    camt_path: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let out_w = &std::io::stdout();
+
+    let cli = Cli::parse();
+
+    let hulls = read_camt_file(&cli.camt_path)?;
+    hulls.write(out_w)
+}
+
+/// Read the camt file, decoding it according to the encoding declared in the XML prolog, if any.
+pub(crate) fn read_camt_file(path: &Path) -> Result<Hulls> {
+    let content = std::fs::read(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    camt::parse(path, &decode_xml(&content))
+}
+
+#[path = "../hull.rs"]
+mod hull;
+use hull::Hulls;
+
+#[path = "../camt.rs"]
+mod camt;
+
+#[path = "../decode.rs"]
+mod decode;
+
+#[path = "../xml.rs"]
+mod xml;
+use xml::decode_xml;
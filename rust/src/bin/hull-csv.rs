@@ -1,54 +1,550 @@
 use clap::Parser;
-use color_eyre::eyre::{Context, Result};
+use color_eyre::eyre::{Context, Result, eyre};
+use encoding_rs::Encoding;
+use regex::Regex;
+use rust_decimal::Decimal;
 use slugify::slugify;
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
 };
 
+const COLUMN_PREFIX: &str = "column-";
+const DECIMAL_SUFFIX: &str = "-decimal";
+const ISO_SUFFIX: &str = "-iso";
+const SECTION: &str = "section";
+const SIGNED_AMOUNT: &str = "signed-amount";
+const UTF8_BOM: &str = "\u{feff}";
+
 #[derive(Parser)]
 #[command(version, about = "Hull an CSV file for import into limabean-harvest", long_about = None)]
 struct Cli {
+    #[command(flatten)]
+    dialect: Dialect,
+
+    #[command(flatten)]
+    layout: Layout,
+
+    #[command(flatten)]
+    normalisation: Normalisation,
+
     /// File to ingest
     csv_path: PathBuf,
 }
 
+/// How the CSV file is to be read, where this differs from the defaults.
+#[derive(clap::Args, Debug)]
+struct Dialect {
+    /// Field delimiter, a single ASCII character, or `\t` or `tab` for tab
+    #[clap(long, default_value = ",", value_parser = parse_ascii_char)]
+    delimiter: u8,
+
+    /// Quote character
+    #[clap(long, default_value = "\"", value_parser = parse_ascii_char)]
+    quote: u8,
+
+    /// Escape character for quotes within quoted fields, instead of doubling them
+    #[clap(long, value_parser = parse_ascii_char)]
+    escape: Option<u8>,
+
+    /// Lines starting with this character are ignored
+    #[clap(long, value_parser = parse_ascii_char)]
+    comment: Option<u8>,
+
+    /// Character encoding of the file, e.g. windows-1252 or iso-8859-1
+    #[clap(long, default_value = "utf-8", value_parser = parse_encoding)]
+    encoding: &'static Encoding,
+
+    /// Allow records to have differing numbers of fields
+    #[clap(long)]
+    flexible: bool,
+
+    /// Trim whitespace from fields and column names
+    #[clap(long)]
+    trim: bool,
+}
+
+/// Where the table is within the file, for files with a preamble or footer.
+#[derive(clap::Args, Debug)]
+struct Layout {
+    /// Number of preamble lines to skip before the header
+    #[clap(long, default_value_t = 0)]
+    skip_lines: usize,
+
+    /// Skip preamble lines until the header, which is the first line matching this regex
+    #[clap(long)]
+    header_pattern: Option<Regex>,
+
+    /// Regex with named groups to capture from preamble lines into the hull header, may be repeated
+    #[clap(long)]
+    preamble_capture: Vec<Regex>,
+
+    /// Drop rows matching this regex, such as totals, with fields joined by the delimiter
+    #[clap(long)]
+    footer_pattern: Option<Regex>,
+
+    /// The file has no header line, so columns are named by --columns, or else numbered
+    #[clap(long)]
+    no_header: bool,
+
+    /// Comma-separated column names, overriding those in the header
+    #[clap(long, value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Split into sections, each a separate hull, at lines matching this regex, e.g. `^$` for blank lines,
+    /// with any content before the first which isn't a table read as key/value metadata for every section
+    #[clap(long)]
+    section_pattern: Option<Regex>,
+}
+
+/// Normalised companion fields to add to each transaction, leaving the original fields as they are.
+#[derive(clap::Args, Debug)]
+struct Normalisation {
+    /// Column of amounts to normalise as a canonical decimal `<column>-decimal`, may be repeated
+    #[clap(long)]
+    decimal: Vec<String>,
+
+    /// Decimal separator of amounts, with any other separator ignored, e.g. `,` for 1.234,56
+    #[clap(long, default_value = ".")]
+    decimal_separator: char,
+
+    /// Column of debit amounts, to be combined with --credit into the decimal `signed-amount`
+    #[clap(long)]
+    debit: Option<String>,
+
+    /// Column of credit amounts, to be combined with --debit into the decimal `signed-amount`
+    #[clap(long)]
+    credit: Option<String>,
+
+    /// Column of dates to normalise as ISO 8601 `<column>-iso`, according to --date-format, may be repeated
+    #[clap(long, requires = "date_format")]
+    date: Vec<String>,
+
+    /// Format of dates to normalise, e.g. dd/MM/yyyy or d MMM yy
+    #[clap(long)]
+    date_format: Option<DateFormat>,
+}
+
+impl Normalisation {
+    fn normalise(&self, txn: &mut HashMap<String, String>) {
+        for column in self.decimal.iter() {
+            if let Some(decimal) = self.decimal_field(txn, column) {
+                txn.insert(format!("{}{}", column, DECIMAL_SUFFIX), decimal.to_string());
+            }
+        }
+
+        if self.debit.is_some() || self.credit.is_some() {
+            // a blank or missing debit or credit is zero, whereas an unparseable one is omitted
+            let debit = self
+                .debit
+                .as_ref()
+                .map(|column| self.decimal_field(txn, column));
+            let credit = self
+                .credit
+                .as_ref()
+                .map(|column| self.decimal_field(txn, column));
+            let blank = |column: &Option<String>| {
+                column
+                    .as_ref()
+                    .and_then(|column| txn.get(column))
+                    .is_none_or(|value| value.trim().is_empty())
+            };
+            match (debit.flatten(), credit.flatten()) {
+                (Some(debit), Some(credit)) => Some(credit.abs() - debit.abs()),
+                (Some(debit), None) if blank(&self.credit) => Some(-debit.abs()),
+                (None, Some(credit)) if blank(&self.debit) => Some(credit.abs()),
+                _ => None,
+            }
+            .into_iter()
+            .for_each(|amount| {
+                txn.insert(SIGNED_AMOUNT.to_string(), amount.to_string());
+            });
+        }
+
+        if let Some(date_format) = self.date_format.as_ref() {
+            for column in self.date.iter() {
+                if let Some(value) = txn.get(column) {
+                    match date_format.parse(value) {
+                        Some(date) => {
+                            txn.insert(format!("{}{}", column, ISO_SUFFIX), iso_date(date));
+                        }
+                        None => eprintln!("warning: invalid date {} in column {}", value, column),
+                    }
+                }
+            }
+        }
+    }
+
+    /// The normalised value of the column, if present and not blank, with a warning if unparseable.
+    fn decimal_field(&self, txn: &HashMap<String, String>, column: &str) -> Option<Decimal> {
+        let value = txn.get(column).filter(|value| !value.trim().is_empty())?;
+        let decimal = parse_decimal(value, self.decimal_separator);
+        if decimal.is_none() {
+            eprintln!("warning: invalid amount {} in column {}", value, column);
+        }
+        decimal
+    }
+}
+
+fn parse_ascii_char(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => match s.as_bytes() {
+            [c] if c.is_ascii() => Ok(*c),
+            _ => Err(format!("expected a single ASCII character, found {}", s)),
+        },
+    }
+}
+
+fn parse_encoding(s: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(s.as_bytes()).ok_or_else(|| format!("unknown encoding {}", s))
+}
+
 fn main() -> Result<()> {
     let out_w = &std::io::stdout();
 
     let cli = Cli::parse();
 
-    let hulls = Hulls(vec![read_csv_file(&cli.csv_path)?]);
+    let mut hulls = read_csv_file(&cli.csv_path, &cli.dialect, &cli.layout)?;
+    for hull in hulls.0.iter_mut() {
+        hull.txns
+            .iter_mut()
+            .for_each(|txn| cli.normalisation.normalise(txn));
+    }
     hulls.write(out_w)
 }
 
-pub(crate) fn read_csv_file(path: &Path) -> Result<Hull> {
-    let csv_file = std::fs::File::open(path)
+/// Read the CSV file as one hull per section, or a single hull if not split into sections.
+pub(crate) fn read_csv_file(path: &Path, dialect: &Dialect, layout: &Layout) -> Result<Hulls> {
+    let csv_bytes = std::fs::read(path)
         .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
-    let mut rdr = csv::Reader::from_reader(csv_file);
-    let column_names = rdr
-        .headers()?
+    let csv_content = dialect
+        .encoding
+        .decode_without_bom_handling_and_without_replacement(&csv_bytes)
+        .ok_or_else(|| {
+            eyre!(
+                "Failed to decode {} as {}",
+                path.to_string_lossy(),
+                dialect.encoding.name()
+            )
+        })?;
+    let csv_content = csv_content.strip_prefix(UTF8_BOM).unwrap_or(&csv_content);
+
+    let Some(section_pattern) = layout.section_pattern.as_ref() else {
+        return read_csv_section(path, csv_content, None, 0, dialect, layout)
+            .map(|hull| Hulls(vec![hull]));
+    };
+
+    let sections = split_sections(csv_content, section_pattern);
+    // sections separated by lines such as blank ones, rather than by titles
+    let separated = sections
         .iter()
-        .map(|column_name| slugify(column_name, "", "-", None))
-        .collect::<Vec<_>>();
+        .all(|section| section.title.is_none_or(|title| title.trim().is_empty()));
+    let mut hulls = Vec::default();
+    // metadata from the content before the first section, where this isn't itself a table
+    let mut file_hdr = HashMap::default();
+    for (i, section) in sections.into_iter().enumerate() {
+        if section.content.trim().is_empty() {
+            continue;
+        }
+        let leading = i == 0;
+        if leading && !is_leading_table(section.content, separated, layout) {
+            file_hdr = read_preamble_metadata(section.content, dialect, layout);
+            continue;
+        }
+
+        let mut hull = read_csv_section(
+            path,
+            section.content,
+            section.title,
+            section.first_line,
+            dialect,
+            layout,
+        )?;
+        if leading && hull.txns.is_empty() {
+            // a header alone is not a table
+            file_hdr = read_preamble_metadata(section.content, dialect, layout);
+            continue;
+        }
+        if let Some(captures) = section
+            .title
+            .and_then(|title| section_pattern.captures(title))
+        {
+            for name in section_pattern.capture_names().flatten() {
+                if let Some(value) = captures.name(name) {
+                    hull.hdr
+                        .insert(name.to_string(), value.as_str().to_string());
+                }
+            }
+        }
+        for (k, v) in file_hdr.iter() {
+            hull.hdr.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+        hulls.push(hull);
+    }
+
+    if hulls.is_empty() {
+        eprintln!(
+            "warning: no table found in {} with section pattern {}",
+            path.to_string_lossy(),
+            section_pattern
+        );
+    }
+
+    Ok(Hulls(hulls))
+}
+
+/// Whether the content before the first section is a table, rather than metadata.
+///
+/// This is so if its header is found by `--header-pattern`, or else if the sections are separated rather than titled.
+fn is_leading_table(content: &str, separated: bool, layout: &Layout) -> bool {
+    layout.header_pattern.as_ref().map_or(separated, |header| {
+        content.lines().any(|line| header.is_match(line.trim_end()))
+    })
+}
+
+/// A section of the file, starting after its title line, if any.
+struct Section<'a> {
+    title: Option<&'a str>,
+    content: &'a str,
+    /// The number of lines in the file before the content
+    first_line: usize,
+}
+
+/// Split the content into sections at each line matching `section_pattern`.
+fn split_sections<'a>(content: &'a str, section_pattern: &Regex) -> Vec<Section<'a>> {
+    let mut sections = Vec::default();
+    let mut title = None;
+    let mut start = 0;
+    let mut first_line = 0;
+    let mut offset = 0;
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        let next_offset = offset + line.len();
+        let line = line.trim_end();
+        if section_pattern.is_match(line) {
+            sections.push(Section {
+                title,
+                content: &content[start..offset],
+                first_line,
+            });
+            title = Some(line);
+            start = next_offset;
+            first_line = i + 1;
+        }
+        offset = next_offset;
+    }
+    sections.push(Section {
+        title,
+        content: &content[start..],
+        first_line,
+    });
+    sections
+}
+
+/// Read the content before the first section as key/value metadata, e.g. `Account,12-3456` or `Currency: GBP`.
+///
+/// Each line is a key, slugified, followed by a value either in the next non-empty field or after a colon.
+/// Values captured by `--preamble-capture` take precedence.
+fn read_preamble_metadata(
+    content: &str,
+    dialect: &Dialect,
+    layout: &Layout,
+) -> HashMap<String, String> {
+    let mut hdr = HashMap::default();
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .escape(dialect.escape)
+        .comment(dialect.comment)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(content.as_bytes());
+    for record in rdr.records() {
+        let Ok(record) = record else {
+            continue;
+        };
+        let mut fields = record
+            .iter()
+            .map(str::trim)
+            .filter(|field| !field.is_empty());
+        let key_value = match (fields.next(), fields.next()) {
+            (Some(key), Some(value)) => Some((key, value)),
+            (Some(field), None) => field
+                .split_once(':')
+                .map(|(key, value)| (key, value.trim())),
+            _ => None,
+        };
+        if let Some((key, value)) = key_value {
+            let key = slugify(key, "", "-", None);
+            if !key.is_empty() && !value.is_empty() {
+                hdr.insert(key, value.to_string());
+            }
+        }
+    }
+    capture_into(content, &layout.preamble_capture, &mut hdr);
+    hdr
+}
+
+/// Insert values of named groups of any of the `captures` matching lines of the content.
+fn capture_into(content: &str, captures: &[Regex], hdr: &mut HashMap<String, String>) {
+    for line in content.lines() {
+        for capture in captures.iter() {
+            if let Some(captured) = capture.captures(line) {
+                for name in capture.capture_names().flatten() {
+                    if let Some(value) = captured.name(name) {
+                        hdr.insert(name.to_string(), value.as_str().to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Read a single table with optional preamble as a hull.
+///
+/// Any section title is included in the header as `section`, and is also subject to preamble capture.
+fn read_csv_section(
+    path: &Path,
+    csv_content: &str,
+    title: Option<&str>,
+    first_line: usize,
+    dialect: &Dialect,
+    layout: &Layout,
+) -> Result<Hull> {
+    let (preamble, table) = split_preamble(csv_content, layout)
+        .ok_or_else(|| eyre!("Failed to find header in {}", path.to_string_lossy()))?;
+
+    let mut hdr = HashMap::default();
+    if let Some(title) = title.filter(|title| !title.is_empty()) {
+        hdr.insert(SECTION.to_string(), title.to_string());
+        capture_into(title, &layout.preamble_capture, &mut hdr);
+    }
+    capture_into(preamble, &layout.preamble_capture, &mut hdr);
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .escape(dialect.escape)
+        .comment(dialect.comment)
+        // record lengths are checked below, since footer rows commonly differ
+        .flexible(true)
+        .has_headers(!layout.no_header)
+        .trim(if dialect.trim {
+            csv::Trim::All
+        } else {
+            csv::Trim::None
+        })
+        .from_reader(table.as_bytes());
+    // what the width of each record is checked against
+    let width_source = if layout.no_header && layout.columns.is_empty() {
+        "the first record"
+    } else {
+        "the header"
+    };
+    let mut column_names = if !layout.columns.is_empty() {
+        column_keys(layout.columns.iter().map(String::as_str))
+    } else if layout.no_header {
+        // numbered according to the first record
+        Vec::default()
+    } else {
+        column_keys(rdr.headers()?.iter())
+    };
+    let delimiter = char::from(dialect.delimiter).to_string();
     let mut transactions = Vec::<HashMap<String, String>>::default();
     for transaction in rdr.records() {
         // The iterator yields Result<StringRecord, Error>, so we check the
         // error here..
+        let transaction = transaction?;
+        if layout.footer_pattern.as_ref().is_some_and(|footer| {
+            footer.is_match(&transaction.iter().collect::<Vec<_>>().join(&delimiter))
+        }) {
+            continue;
+        }
+        if column_names.is_empty() {
+            column_names = (1..=transaction.len())
+                .map(|i| format!("{}{}", COLUMN_PREFIX, i))
+                .collect();
+        }
+        if transaction.len() != column_names.len() {
+            let mismatch = format!(
+                "found record with {} fields, but {} has {}, at line {} in {}",
+                transaction.len(),
+                width_source,
+                column_names.len(),
+                first_line
+                    + preamble.lines().count()
+                    + transaction
+                        .position()
+                        .map_or(0, |position| line_at(table, position.byte() as usize)),
+                path.to_string_lossy()
+            );
+            if dialect.flexible && transaction.len() > column_names.len() {
+                eprintln!(
+                    "warning: {}, so dropping fields beyond {}",
+                    mismatch,
+                    column_names.len()
+                );
+            } else if dialect.flexible {
+                eprintln!("warning: {}", mismatch);
+            } else {
+                return Err(eyre!("{}, try --flexible", mismatch));
+            }
+        }
         let transaction = column_names
             .iter()
-            .zip(transaction?.iter())
+            .zip(transaction.iter())
             .map(|(k, v)| (k.clone(), v.to_string()))
             .collect::<HashMap<_, _>>();
         transactions.push(transaction);
     }
 
     Ok(Hull {
-        hdr: HashMap::default(),
+        hdr,
         txns: transactions,
+        positions: Vec::default(),
     })
 }
 
+/// The line number of the record at the byte offset, which may be that of the end of the previous line.
+///
+/// This is counted here, since the reader's own line count is awry for CRLF line endings.
+fn line_at(content: &str, offset: usize) -> usize {
+    let rest = &content[offset..];
+    let start = offset + rest.len() - rest.trim_start_matches(['\r', '\n']).len();
+    content[..start].matches('\n').count() + 1
+}
+
+/// Split the content into the preamble and the table, which starts with its header line.
+fn split_preamble<'a>(content: &'a str, layout: &Layout) -> Option<(&'a str, &'a str)> {
+    if layout.skip_lines == 0 && layout.header_pattern.is_none() {
+        return Some(("", content));
+    }
+
+    let mut header_start = 0;
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        if i >= layout.skip_lines
+            && layout
+                .header_pattern
+                .as_ref()
+                .is_none_or(|header| header.is_match(line.trim_end()))
+        {
+            return Some(content.split_at(header_start));
+        }
+        header_start += line.len();
+    }
+    None
+}
+
+#[path = "../columns.rs"]
+mod columns;
+use columns::column_keys;
+
 #[path = "../hull.rs"]
 mod hull;
 use hull::{Hull, Hulls};
+
+#[path = "../normalise.rs"]
+mod normalise;
+use normalise::{DateFormat, iso_date, parse_decimal};
+
+#[path = "../year.rs"]
+mod year;
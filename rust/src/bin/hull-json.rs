@@ -0,0 +1,147 @@
+use clap::Parser;
+use color_eyre::eyre::{Context, Result, eyre};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+const UTF8_BOM: &str = "\u{feff}";
+
+#[derive(Parser)]
+#[command(version, about = "Hull a JSON file for import into limabean-harvest", long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    selection: Selection,
+
+    /// File to ingest
+    json_file: PathBuf,
+}
+
+/// Which parts of the JSON document are hulled, each selected by a JSONPath expression (RFC 9535).
+#[derive(clap::Args, Debug)]
+struct Selection {
+    /// Root of each hull, e.g. `$.statements[*]`, default the whole document
+    #[clap(long, default_value = "$")]
+    hull: JsonPath,
+
+    /// Header objects or fields, relative to the hull root, e.g. `$.account`, may be repeated
+    #[clap(long)]
+    header: Vec<JsonPath>,
+
+    /// Transactions, or an array of them, relative to the hull root, e.g. `$.transactions[*]`
+    #[clap(long)]
+    transactions: JsonPath,
+}
+
+fn main() -> Result<()> {
+    let out_w = &std::io::stdout();
+
+    let cli = Cli::parse();
+
+    let hulls = read_json_file(&cli.json_file, &cli.selection)?;
+    hulls.write(out_w)
+}
+
+pub(crate) fn read_json_file(path: &Path, selection: &Selection) -> Result<Hulls> {
+    let content = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    let document =
+        serde_json::from_str::<Value>(content.strip_prefix(UTF8_BOM).unwrap_or(&content))
+            .wrap_err_with(|| format!("Failed to parse JSON in {}", path.to_string_lossy()))?;
+
+    selection
+        .hull
+        .query(&document)
+        .iter()
+        .map(|root| read_hull(path, root, selection))
+        .collect::<Result<Vec<_>>>()
+        .map(Hulls)
+}
+
+fn read_hull(path: &Path, root: &Value, selection: &Selection) -> Result<Hull> {
+    let mut hdr = HashMap::default();
+    for header in selection.header.iter() {
+        for node in header.query_located(root).iter() {
+            // an object contributes its fields, anything else is keyed by its own name
+            let key = match node.node() {
+                Value::Object(_) => String::default(),
+                _ => node
+                    .location()
+                    .last()
+                    .map(|element| element.to_string())
+                    .unwrap_or_default(),
+            };
+            flatten_into(&key, node.node(), &mut hdr);
+        }
+    }
+
+    let selected = selection.transactions.query(root).all();
+    let txn_values = match selected.as_slice() {
+        [Value::Array(items)] => items.iter().collect(),
+        _ => selected,
+    };
+    let txns = txn_values
+        .into_iter()
+        .map(|value| match value {
+            Value::Object(_) => {
+                let mut txn = HashMap::default();
+                flatten_into("", value, &mut txn);
+                Ok(txn)
+            }
+            _ => Err(eyre!(
+                "transaction selected by {} in {} is not an object: {}",
+                selection.transactions,
+                path.to_string_lossy(),
+                value
+            )),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Hull {
+        hdr,
+        txns,
+        positions: Vec::default(),
+    })
+}
+
+/// Flatten the value into `fields`, with nested objects and arrays as dotted keys, e.g. `amount.value` or `tags.0`.
+///
+/// Numbers are as written in the file, and nulls are omitted.
+fn flatten_into(key: &str, value: &Value, fields: &mut HashMap<String, String>) {
+    let child_key = |name: &str| {
+        if key.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", key, name)
+        }
+    };
+
+    match value {
+        Value::Null => (),
+        Value::Bool(b) => {
+            fields.insert(key.to_string(), b.to_string());
+        }
+        Value::Number(n) => {
+            fields.insert(key.to_string(), n.to_string());
+        }
+        Value::String(s) => {
+            fields.insert(key.to_string(), s.clone());
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_into(&child_key(&i.to_string()), item, fields);
+            }
+        }
+        Value::Object(map) => {
+            for (name, item) in map.iter() {
+                flatten_into(&child_key(name), item, fields);
+            }
+        }
+    }
+}
+
+#[path = "../hull.rs"]
+mod hull;
+use hull::{Hull, Hulls};
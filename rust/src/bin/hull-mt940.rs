@@ -0,0 +1,40 @@
+use clap::Parser;
+use color_eyre::eyre::{Context, Result};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = "Hull a SWIFT MT940 or MT942 file for import into limabean-harvest", long_about = None)]
+struct Cli {
+    /// File to ingest
+    mt940_path: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let out_w = &std::io::stdout();
+
+    let cli = Cli::parse();
+
+    let hulls = read_mt940_file(&cli.mt940_path)?;
+    hulls.write(out_w)
+}
+
+/// Read the MT940 file, which if not UTF-8 is taken to be Windows-1252, as many banks write.
+pub(crate) fn read_mt940_file(path: &Path) -> Result<Hulls> {
+    let content = std::fs::read(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    mt940::parse(path, &decode(&content, None))
+}
+
+#[path = "../decode.rs"]
+mod decode;
+use decode::decode;
+
+#[path = "../hull.rs"]
+mod hull;
+use hull::Hulls;
+
+#[path = "../mt940.rs"]
+mod mt940;
+
+#[path = "../year.rs"]
+mod year;
@@ -1,36 +1,72 @@
 use clap::Parser;
 use color_eyre::eyre::{Context, Result, eyre};
-use regex::Regex;
+use regex::bytes::Regex;
 use std::path::PathBuf;
-use std::{fs::read_to_string, path::Path, sync::LazyLock};
+use std::{fs::read, path::Path, sync::LazyLock};
 
 const ACCTID: &str = "acctid";
 const BALAMT: &str = "balamt";
-const CURDEF: &str = "curdef";
+const BROKERID: &str = "brokerid";
+const CHARSET: &str = "charset";
+const CORRECTACTION: &str = "correctaction";
+const CORRECTFITID: &str = "correctfitid";
+const CURRATE: &str = "currate";
+const CURRENCY: &str = "currency";
+const DELETE: &str = "DELETE";
+const DELETED: &str = "deleted";
 const DTASOF: &str = "dtasof";
+const DTEND: &str = "dtend";
 const DTPOSTED: &str = "dtposted";
+const DTSTART: &str = "dtstart";
+const DTTRADE: &str = "dttrade";
+const ENCODING: &str = "encoding";
 const FITID: &str = "fitid";
-const MEMO: &str = "memo";
-const NAME: &str = "name";
-const OFXHEADER: &str = "ofxheader";
+const INVPOSTYPE: &str = "invpostype";
+const INVTRNTYPE: &str = "invtrntype";
+const MSGSET: &str = "msgset";
+const ORIGAMT: &str = "origamt";
+const ORIGCUR: &str = "origcur";
+const ORIGCURRENCY: &str = "origcurrency";
 const PAYEE: &str = "payee";
+const PAYEE_NAME: &str = "payee.name";
+const SECID_UNIQUEID: &str = "secid.uniqueid";
+const SECINFOTYPE: &str = "secinfotype";
+const SECNAME: &str = "secname";
+const TICKER: &str = "ticker";
+const TOTAL: &str = "total";
 const TRNAMT: &str = "trnamt";
-const TRNTYPE: &str = "trntype";
-const VERSION: &str = "version";
 
-static BLANK_LINE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new("\r?\n\\s*\r?\n").unwrap());
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
 
-static OFX1_HEADER_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"\bOFXHEADER:(1[0-9][0-9])\b[^<]*\bVERSION:([0-9]+)"#).unwrap());
+static OFX1_HEADER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\bOFXHEADER\s*:\s*(1[0-9][0-9])\b[^<]*\bVERSION\s*:\s*([0-9]+)"#).unwrap()
+});
 
 static OFX2_HEADER_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"<\?xml[^>]*\?>\s*<\?OFX\s+OFXHEADER="(2[0-9][0-9])[^>]*\bVERSION="([0-9]+)""#)
+    Regex::new(r#"(<\?xml[^>]*\?>\s*)?<\?OFX\s+OFXHEADER="(2[0-9][0-9])[^>]*\bVERSION="([0-9]+)""#)
         .unwrap()
 });
 
+/// The root element, for files whose header is missing or mangled.
+static OFX_ROOT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?i)<OFX>"#).unwrap());
+
+static OFX_ROOT_END_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?i)</OFX>"#).unwrap());
+
+/// The first element with text, which is followed by its end tag only in XML.
+static FIRST_TEXT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<[A-Za-z0-9.]+>[^<]*[^<\s][^<]*(</)?"#).unwrap());
+
 #[derive(Parser)]
 #[command(version, about = "Hull an OFX file for import into limabean-harvest", long_about = None)]
 struct Cli {
+    /// Time zone for dates of datetimes with explicit offset: local, UTC, or +HH:MM
+    #[clap(long)]
+    tz: Option<Tz>,
+
+    /// Recover from common malformations in OFX1, with a warning for each
+    #[clap(long)]
+    lenient: bool,
+
     /// File to ingest
     ofx_path: PathBuf,
 }
@@ -40,45 +76,131 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    let hulls = read_ofx_file(&cli.ofx_path)?;
+    let mut hulls = read_ofx_file(&cli.ofx_path, cli.lenient)?;
+    for hull in hulls.0.iter_mut() {
+        std::iter::once(&mut hull.hdr)
+            .chain(hull.txns.iter_mut())
+            .chain(hull.positions.iter_mut())
+            .for_each(|fields| datetime::normalise_fields(fields, cli.tz));
+    }
     hulls.write(out_w)
 }
 
-pub(crate) fn read_ofx_file(path: &Path) -> Result<Hulls> {
-    let content = read_to_string(path)
-        .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
-    if let Some(captures) = OFX1_HEADER_RE.captures(&content) {
-        if let Some(m) = BLANK_LINE_RE.find(&content) {
-            let ofxheader = captures.get(1).unwrap().as_str();
-            let version = captures.get(2).unwrap().as_str();
-            ofx1::parse(path, &content[m.end()..], ofxheader, version)
+/// Read the OFX file as bytes, since its encoding is declared in the header.
+///
+/// The file may comprise several complete OFX documents back to back, each with its own header,
+/// in which case the hulls of all documents are returned.
+pub(crate) fn read_ofx_file(path: &Path, lenient: bool) -> Result<Hulls> {
+    let content =
+        read(path).wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    let documents = split_documents(&content);
+    if let [(preceding_lines, document)] = documents.as_slice() {
+        return parse_ofx_document(path, *preceding_lines, document, lenient);
+    }
+
+    let mut hulls = Vec::default();
+    for (i, (preceding_lines, document)) in documents.into_iter().enumerate() {
+        hulls.extend(
+            parse_ofx_document(path, preceding_lines, document, lenient)
+                .wrap_err_with(|| {
+                    format!(
+                        "Failed to hull OFX document {} in {}",
+                        i + 1,
+                        path.to_string_lossy()
+                    )
+                })?
+                .0,
+        );
+    }
+    Ok(Hulls(hulls))
+}
+
+/// Split content into OFX documents, each ending with the end tag of its root element, and
+/// each with the number of lines preceding it in the file.
+///
+/// Any content after the last root end tag, such as a truncated document, is also a document.
+fn split_documents(content: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut documents = Vec::default();
+    let mut start = 0;
+    let mut preceding_lines = 0;
+    for m in OFX_ROOT_END_RE.find_iter(content) {
+        let document = &content[start..m.end()];
+        documents.push((preceding_lines, document));
+        preceding_lines += document.iter().filter(|b| **b == b'\n').count();
+        start = m.end();
+    }
+    if documents.is_empty() || !content[start..].trim_ascii().is_empty() {
+        documents.push((preceding_lines, &content[start..]));
+    }
+    documents
+}
+
+/// Parse a single OFX document.
+///
+/// Where the header is missing or mangled, as in some QuickBooks Web Connect files, the body
+/// is sniffed to determine whether it is SGML or XML.
+fn parse_ofx_document(
+    path: &Path,
+    preceding_lines: usize,
+    content: &[u8],
+    lenient: bool,
+) -> Result<Hulls> {
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+    if OFX1_HEADER_RE.is_match(content) {
+        // the header is everything before the first element
+        if let Some(body_start) = content.iter().position(|b| *b == b'<') {
+            ofx1::parse(
+                path,
+                preceding_lines,
+                &content[..body_start],
+                &content[body_start..],
+                lenient,
+            )
         } else {
             Err(eyre!("failed to find end of OFX1 header in {:?}", path))
         }
-    } else if let Some(captures) = OFX2_HEADER_RE.captures(&content) {
-        let ofxheader = captures.get(1).unwrap().as_str();
-        let version = captures.get(2).unwrap().as_str();
-        ofx2::parse(path, &content, ofxheader, version)
+    } else if OFX2_HEADER_RE.is_match(content) {
+        ofx2::parse(path, content)
+    } else if let Some(root) = OFX_ROOT_RE.find(content) {
+        let body = &content[root.start()..];
+        let is_xml = FIRST_TEXT_RE
+            .captures(body)
+            .is_some_and(|captures| captures.get(1).is_some());
+        if is_xml {
+            ofx2::parse(path, body)
+        } else {
+            ofx1::parse(
+                path,
+                preceding_lines,
+                &content[..root.start()],
+                body,
+                lenient,
+            )
+        }
     } else {
         Err(eyre!("unrecognised file content in {:?}", path))
     }
 }
 
-fn truncate_yyyymmdd(s: String) -> String {
-    const MAXLEN: usize = 8;
-    if s.len() > MAXLEN {
-        s[..MAXLEN].to_string()
-    } else {
-        s
-    }
-}
+#[path = "../datetime.rs"]
+mod datetime;
+use datetime::Tz;
+
+#[path = "../decode.rs"]
+mod decode;
 
 #[path = "../hull.rs"]
 mod hull;
 use hull::Hulls;
 
+#[path = "../ofx.rs"]
+mod ofx;
+
 #[path = "../ofx1.rs"]
 mod ofx1;
 
 #[path = "../ofx2.rs"]
 mod ofx2;
+
+#[path = "../xml.rs"]
+mod xml;
@@ -0,0 +1,45 @@
+use clap::Parser;
+use color_eyre::eyre::{Context, Result};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = "Hull a QIF file for import into limabean-harvest", long_about = None)]
+struct Cli {
+    /// Order of day, month, and year in dates: mdy, dmy, or ymd
+    #[clap(long, default_value = "mdy")]
+    date_order: DateOrder,
+
+    /// File to ingest
+    qif_path: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let out_w = &std::io::stdout();
+
+    let cli = Cli::parse();
+
+    let hulls = read_qif_file(&cli.qif_path, cli.date_order)?;
+    hulls.write(out_w)
+}
+
+/// Read the QIF file, which if not UTF-8 is taken to be Windows-1252, as Quicken writes.
+pub(crate) fn read_qif_file(path: &Path, date_order: DateOrder) -> Result<Hulls> {
+    let content = std::fs::read(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    qif::parse(path, &decode(&content, None), date_order)
+}
+
+#[path = "../decode.rs"]
+mod decode;
+use decode::decode;
+
+#[path = "../hull.rs"]
+mod hull;
+use hull::Hulls;
+
+#[path = "../qif.rs"]
+mod qif;
+use qif::DateOrder;
+
+#[path = "../year.rs"]
+mod year;
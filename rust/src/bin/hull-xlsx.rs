@@ -0,0 +1,251 @@
+use calamine::{Data, ExcelDateTime, ExcelDateTimeType, Reader, open_workbook_auto};
+use clap::Parser;
+use color_eyre::eyre::{Context, Result, eyre};
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+const COLUMN_PREFIX: &str = "column-";
+const SHEET: &str = "sheet";
+
+static DATE_1904_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<(?:\w+:)?workbookPr\b[^>]*\bdate1904="(?:1|true)""#).unwrap());
+
+#[derive(Parser)]
+#[command(version, about = "Hull a spreadsheet file (XLSX, XLS, XLSB, or ODS) for import into limabean-harvest", long_about = None)]
+struct Cli {
+    /// Sheet to hull, by name or by number counting from 1, may be repeated, default the first
+    #[clap(long, conflicts_with = "all_sheets")]
+    sheet: Vec<String>,
+
+    /// Hull every sheet
+    #[clap(long)]
+    all_sheets: bool,
+
+    /// Row number of the header, counting from 1, default the first non-empty row
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    header_row: Option<u32>,
+
+    /// Column of numeric date serials to convert, where not formatted as dates in the spreadsheet, may be repeated
+    #[clap(long)]
+    date: Vec<String>,
+
+    /// File to ingest
+    spreadsheet_path: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let out_w = &std::io::stdout();
+
+    let cli = Cli::parse();
+
+    let hulls = read_spreadsheet_file(&cli)?;
+    hulls.write(out_w)
+}
+
+/// Read each selected sheet of the spreadsheet as a hull.
+fn read_spreadsheet_file(cli: &Cli) -> Result<Hulls> {
+    let path = &cli.spreadsheet_path;
+    let mut workbook = open_workbook_auto(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    let sheet_names = workbook.sheet_names();
+
+    let selected = if cli.all_sheets {
+        sheet_names.clone()
+    } else if cli.sheet.is_empty() {
+        sheet_names.iter().take(1).cloned().collect()
+    } else {
+        cli.sheet
+            .iter()
+            .map(|sheet| select_sheet(path, sheet, &sheet_names))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let xlsx_1904 = xlsx_date_1904(path);
+    let mut hulls = Vec::default();
+    for sheet_name in selected {
+        let range = workbook.worksheet_range(&sheet_name).wrap_err_with(|| {
+            format!(
+                "Failed to read sheet {} in {}",
+                sheet_name,
+                path.to_string_lossy()
+            )
+        })?;
+        let is_1904 = xlsx_1904.unwrap_or_else(|| inferred_date_1904(&range));
+        hulls.push(read_sheet(path, &sheet_name, &range, is_1904, cli)?);
+    }
+
+    Ok(Hulls(hulls))
+}
+
+/// Whether an XLSX workbook uses the 1904 date system, as do some created by Excel for Mac.
+///
+/// Calamine applies this to cells formatted as dates, but does not expose it for converting
+/// other date serials, so it is read from the workbook part.  None if not XLSX.
+fn xlsx_date_1904(path: &Path) -> Option<bool> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut archive = zip::ZipArchive::new(file).ok()?;
+    let mut workbook = String::default();
+    archive
+        .by_name("xl/workbook.xml")
+        .ok()?
+        .read_to_string(&mut workbook)
+        .ok()?;
+    Some(DATE_1904_RE.is_match(&workbook))
+}
+
+/// Whether the 1904 date system is used, as inferred from any cell formatted as a date, for formats other than XLSX.
+fn inferred_date_1904(range: &calamine::Range<Data>) -> bool {
+    range
+        .used_cells()
+        .find_map(|(_, _, cell)| match cell {
+            Data::DateTime(datetime) if datetime.is_datetime() => Some(
+                *datetime
+                    == ExcelDateTime::new(datetime.as_f64(), ExcelDateTimeType::DateTime, true),
+            ),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
+/// Select a sheet by name, or failing that, by number.
+fn select_sheet(path: &Path, sheet: &str, sheet_names: &[String]) -> Result<String> {
+    if let Some(sheet_name) = sheet_names.iter().find(|sheet_name| *sheet_name == sheet) {
+        return Ok(sheet_name.clone());
+    }
+    sheet
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| sheet_names.get(i).cloned())
+        .ok_or_else(|| {
+            eyre!(
+                "no sheet {} in {}, which has {}",
+                sheet,
+                path.to_string_lossy(),
+                sheet_names.join(", ")
+            )
+        })
+}
+
+fn read_sheet(
+    path: &Path,
+    sheet_name: &str,
+    range: &calamine::Range<Data>,
+    is_1904: bool,
+    cli: &Cli,
+) -> Result<Hull> {
+    let hdr = HashMap::from([(SHEET.to_string(), sheet_name.to_string())]);
+
+    // the range starts at the first non-empty row and column, which may not be the first of the sheet
+    let (first_row, _) = range.start().unwrap_or_default();
+    let header_offset = match cli.header_row {
+        Some(header_row) => (header_row - 1).checked_sub(first_row).ok_or_else(|| {
+            eyre!(
+                "header row {} of sheet {} in {} is empty",
+                header_row,
+                sheet_name,
+                path.to_string_lossy()
+            )
+        })?,
+        None => 0,
+    };
+
+    let mut rows = range.rows().skip(header_offset as usize);
+    let Some(header) = rows.next() else {
+        return Ok(Hull {
+            hdr,
+            txns: Vec::default(),
+            positions: Vec::default(),
+        });
+    };
+
+    let header = header
+        .iter()
+        .map(|cell| cell_string(cell, false, is_1904))
+        .collect::<Vec<_>>();
+    let column_names = column_keys(header.iter().map(String::as_str));
+    let date_columns = column_names
+        .iter()
+        .map(|column_name| cli.date.contains(column_name))
+        .collect::<Vec<_>>();
+
+    let txns = rows
+        .filter(|row| row.iter().any(|cell| !matches!(cell, Data::Empty)))
+        .map(|row| {
+            column_names
+                .iter()
+                .zip(date_columns.iter())
+                .zip(row.iter())
+                .map(|((k, is_date), cell)| (k.clone(), cell_string(cell, *is_date, is_1904)))
+                .collect::<HashMap<_, _>>()
+        })
+        .collect();
+
+    Ok(Hull {
+        hdr,
+        txns,
+        positions: Vec::default(),
+    })
+}
+
+/// Convert the cell to a string, with dates as ISO 8601 and numbers as plain decimals.
+///
+/// Date serials are according to the workbook's date system, 1900 or 1904.
+fn cell_string(cell: &Data, is_date: bool, is_1904: bool) -> String {
+    match cell {
+        Data::Empty => String::default(),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone(),
+        Data::Float(f) if is_date => datetime_string(&ExcelDateTime::new(
+            *f,
+            ExcelDateTimeType::DateTime,
+            is_1904,
+        )),
+        Data::Int(i) if is_date => datetime_string(&ExcelDateTime::new(
+            *i as f64,
+            ExcelDateTimeType::DateTime,
+            is_1904,
+        )),
+        Data::Float(f) => number_string(*f),
+        Data::Int(i) => i.to_string(),
+        Data::Bool(b) => b.to_string(),
+        Data::DateTime(datetime) if datetime.is_datetime() => datetime_string(datetime),
+        Data::DateTime(duration) => number_string(duration.as_f64()),
+        Data::Error(error) => error.to_string(),
+    }
+}
+
+/// Format a date serial as `yyyy-MM-dd`, or `yyyy-MM-ddTHH:mm:ss` if it has a time of day.
+fn datetime_string(datetime: &ExcelDateTime) -> String {
+    let (year, month, day, hour, minute, second, _) = datetime.to_ymd_hms_milli();
+    if (hour, minute, second) == (0, 0, 0) {
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    } else {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, hour, minute, second
+        )
+    }
+}
+
+/// Format a number as a plain decimal, to the 15 significant digits held by spreadsheets.
+///
+/// This avoids floating point artifacts, such as 0.30000000000000004 for a cell showing 0.3.
+fn number_string(f: f64) -> String {
+    Decimal::from_scientific(&format!("{:.14e}", f))
+        .map(|decimal| decimal.normalize().to_string())
+        .unwrap_or_else(|_| f.to_string())
+}
+
+#[path = "../columns.rs"]
+mod columns;
+use columns::column_keys;
+
+#[path = "../hull.rs"]
+mod hull;
+use hull::{Hull, Hulls};
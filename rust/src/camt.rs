@@ -0,0 +1,678 @@
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+use crate::hull::{Hull, Hulls};
+
+const ACCTID: &str = "acctid";
+const ACCT_SVCR_REF: &str = "acct-svcr-ref";
+const AMOUNT: &str = "amount";
+const CURRENCY: &str = "currency";
+const END_TO_END_ID: &str = "end-to-end-id";
+const ENTRY_REF: &str = "entry-ref";
+const ID: &str = "id";
+
+/// End-to-end ID of a payment whose initiator provided none, so not an ID at all.
+const NOT_PROVIDED: &str = "NOTPROVIDED";
+
+/// Credit/debit indicator of a debit, whose amount is negated.
+const DBIT: &str = "DBIT";
+
+/// Balance type codes from which the opening balance is taken, in order of preference.
+const OPENING_BALANCE_CODES: &[&str] = &["OPBD", "PRCD", "OPAV"];
+
+/// Balance type codes from which the closing balance is taken, in order of preference.
+const CLOSING_BALANCE_CODES: &[&str] = &["CLBD", "ITBD", "CLAV", "ITAV"];
+
+// The following model only those parts of camt.052 and camt.053 which are hulled, across the
+// versions in common use.  Elements are matched by local name, so namespaces are immaterial.
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Document {
+    #[serde(rename = "@xmlns")]
+    xmlns: Option<String>,
+    bk_to_cstmr_stmt: Option<BankToCustomerStatement>,
+    bk_to_cstmr_acct_rpt: Option<BankToCustomerAccountReport>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BankToCustomerStatement {
+    grp_hdr: Option<GroupHeader>,
+    #[serde(default)]
+    stmt: Vec<Statement>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BankToCustomerAccountReport {
+    grp_hdr: Option<GroupHeader>,
+    #[serde(default)]
+    rpt: Vec<Statement>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct GroupHeader {
+    msg_id: Option<String>,
+}
+
+/// A statement in camt.053, or a report in camt.052, which are structured alike.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Statement {
+    id: Option<String>,
+    elctrnc_seq_nb: Option<String>,
+    cre_dt_tm: Option<String>,
+    fr_to_dt: Option<FromToDate>,
+    acct: Account,
+    #[serde(default)]
+    bal: Vec<Balance>,
+    #[serde(default)]
+    ntry: Vec<Entry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct FromToDate {
+    fr_dt_tm: Option<String>,
+    to_dt_tm: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Account {
+    id: Option<AccountId>,
+    ccy: Option<String>,
+    nm: Option<String>,
+    ownr: Option<Party>,
+    svcr: Option<Agent>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct AccountId {
+    #[serde(rename = "IBAN")]
+    iban: Option<String>,
+    othr: Option<OtherId>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct OtherId {
+    id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Agent {
+    fin_instn_id: Option<FinancialInstitutionId>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct FinancialInstitutionId {
+    #[serde(rename = "BIC")]
+    bic: Option<String>,
+    #[serde(rename = "BICFI")]
+    bicfi: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Balance {
+    tp: BalanceType,
+    amt: Amount,
+    cdt_dbt_ind: String,
+    dt: Option<DateAndTime>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BalanceType {
+    cd_or_prtry: CodeOrProprietary,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CodeOrProprietary {
+    cd: Option<String>,
+    prtry: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Amount {
+    #[serde(rename = "@Ccy")]
+    ccy: Option<String>,
+    #[serde(rename = "$text")]
+    value: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct DateAndTime {
+    dt: Option<String>,
+    dt_tm: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Entry {
+    ntry_ref: Option<String>,
+    amt: Amount,
+    cdt_dbt_ind: String,
+    rvsl_ind: Option<bool>,
+    sts: Option<Status>,
+    bookg_dt: Option<DateAndTime>,
+    val_dt: Option<DateAndTime>,
+    acct_svcr_ref: Option<String>,
+    bk_tx_cd: Option<BankTransactionCode>,
+    addtl_ntry_inf: Option<String>,
+    #[serde(default)]
+    ntry_dtls: Vec<EntryDetails>,
+}
+
+/// Entry status, which is text up to camt.053.001.04, and a code thereafter.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Status {
+    #[serde(rename = "$text")]
+    text: Option<String>,
+    cd: Option<String>,
+    prtry: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BankTransactionCode {
+    domn: Option<Domain>,
+    prtry: Option<ProprietaryCode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Domain {
+    cd: String,
+    fmly: Family,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Family {
+    cd: String,
+    sub_fmly_cd: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ProprietaryCode {
+    cd: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EntryDetails {
+    #[serde(default)]
+    tx_dtls: Vec<TransactionDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct TransactionDetails {
+    refs: Option<References>,
+    amt: Option<Amount>,
+    amt_dtls: Option<AmountDetails>,
+    cdt_dbt_ind: Option<String>,
+    rltd_pties: Option<RelatedParties>,
+    rltd_agts: Option<RelatedAgents>,
+    rmt_inf: Option<RemittanceInformation>,
+    addtl_tx_inf: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct References {
+    msg_id: Option<String>,
+    acct_svcr_ref: Option<String>,
+    pmt_inf_id: Option<String>,
+    instr_id: Option<String>,
+    end_to_end_id: Option<String>,
+    tx_id: Option<String>,
+    mndt_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct AmountDetails {
+    tx_amt: Option<AmountAndCurrencyExchange>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct AmountAndCurrencyExchange {
+    amt: Amount,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RelatedParties {
+    dbtr: Option<Party>,
+    dbtr_acct: Option<CashAccount>,
+    cdtr: Option<Party>,
+    cdtr_acct: Option<CashAccount>,
+}
+
+/// A party, whose name is direct up to camt.053.001.07, and within `Pty` thereafter.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Party {
+    nm: Option<String>,
+    pty: Option<Box<Party>>,
+}
+
+impl Party {
+    fn name(&self) -> Option<&str> {
+        self.nm
+            .as_deref()
+            .or_else(|| self.pty.as_ref().and_then(|pty| pty.name()))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CashAccount {
+    id: Option<AccountId>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RelatedAgents {
+    dbtr_agt: Option<Agent>,
+    cdtr_agt: Option<Agent>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RemittanceInformation {
+    #[serde(default)]
+    ustrd: Vec<String>,
+    #[serde(default)]
+    strd: Vec<StructuredRemittance>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct StructuredRemittance {
+    cdtr_ref_inf: Option<CreditorReference>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CreditorReference {
+    #[serde(rename = "Ref")]
+    reference: Option<String>,
+}
+
+impl AccountId {
+    fn iban_or_other(&self) -> Option<&str> {
+        self.iban
+            .as_deref()
+            .or_else(|| self.othr.as_ref().and_then(|othr| othr.id.as_deref()))
+    }
+}
+
+impl Agent {
+    fn bic(&self) -> Option<&str> {
+        self.fin_instn_id
+            .as_ref()
+            .and_then(|id| id.bic.as_deref().or(id.bicfi.as_deref()))
+    }
+}
+
+impl DateAndTime {
+    /// The date, without any time.
+    fn date(&self) -> Option<&str> {
+        self.dt.as_deref().or_else(|| {
+            self.dt_tm
+                .as_deref()
+                .map(|dt_tm| dt_tm.split_once('T').map_or(dt_tm, |(dt, _)| dt))
+        })
+    }
+}
+
+impl Status {
+    fn code(&self) -> Option<&str> {
+        self.cd
+            .as_deref()
+            .or(self.prtry.as_deref())
+            .or(self.text.as_deref())
+            .map(str::trim)
+    }
+}
+
+impl BankTransactionCode {
+    /// The domain code as `domain-family-subfamily`, or else the proprietary code.
+    fn code(&self) -> Option<String> {
+        self.domn
+            .as_ref()
+            .map(|domn| format!("{}-{}-{}", domn.cd, domn.fmly.cd, domn.fmly.sub_fmly_cd))
+            .or_else(|| self.prtry.as_ref().and_then(|prtry| prtry.cd.clone()))
+    }
+}
+
+/// The amount, negated for a debit.
+fn signed_amount(amount: &Amount, cdt_dbt_ind: &str) -> String {
+    let value = amount.value.trim();
+    if cdt_dbt_ind == DBIT {
+        format!("-{}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Insert the field if the value is present.
+fn insert<S>(fields: &mut HashMap<String, String>, key: &str, value: Option<S>)
+where
+    S: Into<String>,
+{
+    if let Some(value) = value {
+        fields.insert(key.to_string(), value.into());
+    }
+}
+
+/// Hull each statement or report in the camt.053 or camt.052 document.
+pub(crate) fn parse(path: &Path, content: &str) -> Result<Hulls> {
+    let document = quick_xml::de::from_str::<Document>(content)
+        .wrap_err_with(|| format!("Failed to decode camt XML in {}", path.to_string_lossy()))?;
+
+    let (message, grp_hdr, statements) =
+        match (document.bk_to_cstmr_stmt, document.bk_to_cstmr_acct_rpt) {
+            (Some(stmt), _) => ("camt.053", stmt.grp_hdr, stmt.stmt),
+            (None, Some(rpt)) => ("camt.052", rpt.grp_hdr, rpt.rpt),
+            (None, None) => {
+                return Err(eyre!(
+                    "no BkToCstmrStmt or BkToCstmrAcctRpt found in {:?}",
+                    path
+                ));
+            }
+        };
+
+    let mut hulls = Vec::default();
+    for statement in statements {
+        let mut hdr = HashMap::default();
+        hdr.insert("message".to_string(), message.to_string());
+        insert(&mut hdr, "namespace", document.xmlns.as_deref());
+        insert(
+            &mut hdr,
+            "msg-id",
+            grp_hdr
+                .as_ref()
+                .and_then(|grp_hdr| grp_hdr.msg_id.as_deref()),
+        );
+        statement_hdr(&statement, &mut hdr);
+
+        let currency = statement.acct.ccy.as_deref();
+        let txns = statement
+            .ntry
+            .iter()
+            .flat_map(|entry| entry_txns(entry, currency))
+            .collect();
+
+        hulls.push(Hull {
+            hdr,
+            txns,
+            positions: Vec::default(),
+        });
+    }
+
+    Ok(Hulls(hulls))
+}
+
+fn statement_hdr(statement: &Statement, hdr: &mut HashMap<String, String>) {
+    insert(hdr, "statement-id", statement.id.as_deref());
+    insert(hdr, "sequence-number", statement.elctrnc_seq_nb.as_deref());
+    insert(hdr, "created", statement.cre_dt_tm.as_deref());
+    if let Some(fr_to_dt) = &statement.fr_to_dt {
+        insert(hdr, "from", fr_to_dt.fr_dt_tm.as_deref());
+        insert(hdr, "to", fr_to_dt.to_dt_tm.as_deref());
+    }
+
+    let acct = &statement.acct;
+    insert(
+        hdr,
+        ACCTID,
+        acct.id.as_ref().and_then(AccountId::iban_or_other),
+    );
+    insert(
+        hdr,
+        "iban",
+        acct.id.as_ref().and_then(|id| id.iban.as_deref()),
+    );
+    insert(hdr, CURRENCY, acct.ccy.as_deref());
+    insert(hdr, "account-name", acct.nm.as_deref());
+    insert(hdr, "owner", acct.ownr.as_ref().and_then(Party::name));
+    insert(hdr, "servicer-bic", acct.svcr.as_ref().and_then(Agent::bic));
+
+    // every balance by its type code, for example balance.clbd
+    for bal in statement.bal.iter() {
+        let Some(code) = bal
+            .tp
+            .cd_or_prtry
+            .cd
+            .as_deref()
+            .or(bal.tp.cd_or_prtry.prtry.as_deref())
+        else {
+            continue;
+        };
+        let prefix = format!("balance.{}", code.to_lowercase());
+        hdr.insert(prefix.clone(), signed_amount(&bal.amt, &bal.cdt_dbt_ind));
+        insert(
+            hdr,
+            &format!("{}.date", prefix),
+            bal.dt.as_ref().and_then(DateAndTime::date),
+        );
+        insert(hdr, &format!("{}.currency", prefix), bal.amt.ccy.as_deref());
+    }
+
+    for (name, codes) in [
+        ("opening", OPENING_BALANCE_CODES),
+        ("closing", CLOSING_BALANCE_CODES),
+    ] {
+        if let Some(bal) = codes.iter().find_map(|code| {
+            statement
+                .bal
+                .iter()
+                .find(|bal| bal.tp.cd_or_prtry.cd.as_deref() == Some(code))
+        }) {
+            hdr.insert(
+                format!("{}-balance", name),
+                signed_amount(&bal.amt, &bal.cdt_dbt_ind),
+            );
+            insert(
+                hdr,
+                &format!("{}-balance-date", name),
+                bal.dt.as_ref().and_then(DateAndTime::date),
+            );
+        }
+    }
+}
+
+/// Transactions for the entry, one per transaction details, or just one if there are none.
+fn entry_txns(entry: &Entry, currency: Option<&str>) -> Vec<HashMap<String, String>> {
+    let mut fields = HashMap::default();
+    insert(&mut fields, ENTRY_REF, entry.ntry_ref.as_deref());
+    fields.insert(
+        "entry-amount".to_string(),
+        signed_amount(&entry.amt, &entry.cdt_dbt_ind),
+    );
+    fields.insert("credit-debit".to_string(), entry.cdt_dbt_ind.clone());
+    insert(&mut fields, CURRENCY, entry.amt.ccy.as_deref().or(currency));
+    insert(
+        &mut fields,
+        "reversal",
+        entry.rvsl_ind.map(|rvsl_ind| rvsl_ind.to_string()),
+    );
+    insert(
+        &mut fields,
+        "status",
+        entry.sts.as_ref().and_then(Status::code),
+    );
+    insert(
+        &mut fields,
+        "booking-date",
+        entry.bookg_dt.as_ref().and_then(DateAndTime::date),
+    );
+    insert(
+        &mut fields,
+        "value-date",
+        entry.val_dt.as_ref().and_then(DateAndTime::date),
+    );
+    insert(&mut fields, ACCT_SVCR_REF, entry.acct_svcr_ref.as_deref());
+    insert(
+        &mut fields,
+        "bank-transaction-code",
+        entry.bk_tx_cd.as_ref().and_then(BankTransactionCode::code),
+    );
+    insert(
+        &mut fields,
+        "additional-info",
+        entry.addtl_ntry_inf.as_deref(),
+    );
+
+    let tx_dtls = entry
+        .ntry_dtls
+        .iter()
+        .flat_map(|ntry_dtls| ntry_dtls.tx_dtls.iter())
+        .collect::<Vec<_>>();
+
+    if tx_dtls.is_empty() {
+        fields.insert(
+            AMOUNT.to_string(),
+            signed_amount(&entry.amt, &entry.cdt_dbt_ind),
+        );
+        insert(
+            &mut fields,
+            ID,
+            entry
+                .acct_svcr_ref
+                .as_ref()
+                .or(entry.ntry_ref.as_ref())
+                .cloned(),
+        );
+        return vec![fields];
+    }
+
+    let batch = tx_dtls.len() > 1;
+    tx_dtls
+        .into_iter()
+        .enumerate()
+        .map(|(i, tx_dtls)| {
+            let mut fields = fields.clone();
+            tx_fields(entry, tx_dtls, &mut fields);
+
+            // a batch booking has a reference for the entry as a whole, which is qualified per transaction,
+            // and failing any reference the end-to-end ID is used
+            let own_ref = tx_dtls
+                .refs
+                .as_ref()
+                .is_some_and(|refs| refs.acct_svcr_ref.is_some());
+            let id = match fields.get(ACCT_SVCR_REF).or(fields.get(ENTRY_REF)) {
+                Some(entry_ref) if batch && !own_ref => Some(format!("{}-{}", entry_ref, i + 1)),
+                Some(entry_ref) => Some(entry_ref.clone()),
+                None => fields
+                    .get(END_TO_END_ID)
+                    .filter(|end_to_end_id| *end_to_end_id != NOT_PROVIDED)
+                    .cloned(),
+            };
+            insert(&mut fields, ID, id);
+            fields
+        })
+        .collect()
+}
+
+fn tx_fields(entry: &Entry, tx_dtls: &TransactionDetails, fields: &mut HashMap<String, String>) {
+    let cdt_dbt_ind = tx_dtls.cdt_dbt_ind.as_deref().unwrap_or(&entry.cdt_dbt_ind);
+    let amount = tx_dtls
+        .amt_dtls
+        .as_ref()
+        .and_then(|amt_dtls| amt_dtls.tx_amt.as_ref())
+        .map(|tx_amt| &tx_amt.amt)
+        .or(tx_dtls.amt.as_ref())
+        .unwrap_or(&entry.amt);
+    fields.insert(AMOUNT.to_string(), signed_amount(amount, cdt_dbt_ind));
+    fields.insert("credit-debit".to_string(), cdt_dbt_ind.to_string());
+    insert(fields, CURRENCY, amount.ccy.as_deref());
+
+    if let Some(refs) = &tx_dtls.refs {
+        insert(fields, ACCT_SVCR_REF, refs.acct_svcr_ref.as_deref());
+        insert(fields, "msg-id", refs.msg_id.as_deref());
+        insert(fields, "pmt-inf-id", refs.pmt_inf_id.as_deref());
+        insert(fields, "instr-id", refs.instr_id.as_deref());
+        insert(fields, END_TO_END_ID, refs.end_to_end_id.as_deref());
+        insert(fields, "tx-id", refs.tx_id.as_deref());
+        insert(fields, "mandate-id", refs.mndt_id.as_deref());
+    }
+
+    // the counterparty is the debtor of a credit, and the creditor of a debit
+    if let Some(rltd_pties) = &tx_dtls.rltd_pties {
+        let (party, account) = if cdt_dbt_ind == DBIT {
+            (&rltd_pties.cdtr, &rltd_pties.cdtr_acct)
+        } else {
+            (&rltd_pties.dbtr, &rltd_pties.dbtr_acct)
+        };
+        insert(
+            fields,
+            "counterparty-name",
+            party.as_ref().and_then(Party::name),
+        );
+        insert(
+            fields,
+            "counterparty-iban",
+            account
+                .as_ref()
+                .and_then(|account| account.id.as_ref())
+                .and_then(AccountId::iban_or_other),
+        );
+    }
+    if let Some(rltd_agts) = &tx_dtls.rltd_agts {
+        let agent = if cdt_dbt_ind == DBIT {
+            &rltd_agts.cdtr_agt
+        } else {
+            &rltd_agts.dbtr_agt
+        };
+        insert(
+            fields,
+            "counterparty-bic",
+            agent.as_ref().and_then(Agent::bic),
+        );
+    }
+
+    if let Some(rmt_inf) = &tx_dtls.rmt_inf {
+        if !rmt_inf.ustrd.is_empty() {
+            fields.insert(
+                "remittance-info".to_string(),
+                rmt_inf
+                    .ustrd
+                    .iter()
+                    .map(|ustrd| ustrd.trim())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        }
+        insert(
+            fields,
+            "creditor-reference",
+            rmt_inf.strd.iter().find_map(|strd| {
+                strd.cdtr_ref_inf
+                    .as_ref()
+                    .and_then(|cdtr_ref_inf| cdtr_ref_inf.reference.as_deref())
+            }),
+        );
+    }
+    insert(
+        fields,
+        "additional-tx-info",
+        tx_dtls.addtl_tx_inf.as_deref(),
+    );
+}
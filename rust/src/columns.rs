@@ -0,0 +1,24 @@
+use slugify::slugify;
+
+use super::COLUMN_PREFIX;
+
+/// Slugify column names into keys, numbering any duplicates, e.g. `amount`, `amount-2`.
+///
+/// Columns whose names are blank are keyed by their position, e.g. `column-3`.
+pub(crate) fn column_keys<'a>(column_names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut keys = Vec::<String>::default();
+    for (i, column_name) in column_names.enumerate() {
+        let slug = match slugify(column_name, "", "-", None) {
+            slug if slug.is_empty() => format!("{}{}", COLUMN_PREFIX, i + 1),
+            slug => slug,
+        };
+        let mut key = slug.clone();
+        let mut n = 1;
+        while keys.contains(&key) {
+            n += 1;
+            key = format!("{}-{}", slug, n);
+        }
+        keys.push(key);
+    }
+    keys
+}
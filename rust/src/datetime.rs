@@ -0,0 +1,229 @@
+use color_eyre::eyre::{Result, eyre};
+use std::{collections::HashMap, str::FromStr};
+use time::{Date, Month, PrimitiveDateTime, Time, UtcOffset};
+
+/// Suffix for the key of the original datetime string
+const RAW_SUFFIX: &str = "-raw";
+
+/// Suffix for the key of the time of day, as HHMMSS
+const TIME_SUFFIX: &str = "-time";
+
+/// Time zone into which OFX datetimes having an explicit offset are normalised.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Tz {
+    /// The local time zone, with offset as applicable at each datetime
+    Local,
+    Offset(UtcOffset),
+}
+
+impl FromStr for Tz {
+    type Err = String;
+
+    /// Parse `local`, `UTC`, or an offset such as `+12`, `+12:00`, `+5:30`, or `-0530`.
+    ///
+    /// The local time zone is rejected if its offset cannot be determined on this platform.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time zone {}, expected local, UTC, or +HH:MM", s);
+
+        match s {
+            "local" => UtcOffset::current_local_offset()
+                .map(|_| Tz::Local)
+                .map_err(|e| {
+                    format!(
+                        "local time zone offset cannot be determined ({}), use UTC or +HH:MM",
+                        e
+                    )
+                }),
+            "UTC" | "utc" | "Z" => Ok(Tz::Offset(UtcOffset::UTC)),
+            _ => {
+                let (sign, digits) = match s.split_at_checked(1) {
+                    Some(("+", digits)) => (1, digits),
+                    Some(("-", digits)) => (-1, digits),
+                    _ => return Err(invalid()),
+                };
+                let (hours, minutes) = match digits.split_once(':') {
+                    Some((hours, minutes))
+                        if (1..=2).contains(&hours.len()) && minutes.len() == 2 =>
+                    {
+                        (hours, minutes)
+                    }
+                    Some(_) => return Err(invalid()),
+                    None => match digits.len() {
+                        1 | 2 => (digits, "0"),
+                        4 => digits.split_at(2),
+                        _ => return Err(invalid()),
+                    },
+                };
+                let hours = hours.parse::<i8>().map_err(|_| invalid())?;
+                let minutes = minutes.parse::<i8>().map_err(|_| invalid())?;
+                UtcOffset::from_hms(sign * hours, sign * minutes, 0)
+                    .map(Tz::Offset)
+                    .map_err(|_| invalid())
+            }
+        }
+    }
+}
+
+/// An OFX datetime, `YYYYMMDD[HHMMSS[.XXX]][gmt offset[:tz name]]`, with any time or offset which was present.
+#[derive(Debug)]
+pub(crate) struct OfxDateTime {
+    datetime: PrimitiveDateTime,
+    has_time: bool,
+    offset: Option<UtcOffset>,
+}
+
+impl FromStr for OfxDateTime {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (local, tz) = match s.split_once('[') {
+            Some((local, tz)) => (
+                local,
+                Some(
+                    tz.strip_suffix(']')
+                        .ok_or_else(|| eyre!("unterminated time zone in {}", s))?,
+                ),
+            ),
+            None => (s, None),
+        };
+
+        let number = |range: std::ops::Range<usize>| -> Result<u32> {
+            local
+                .get(range)
+                .and_then(|digits| digits.parse::<u32>().ok())
+                .ok_or_else(|| eyre!("invalid datetime {}", s))
+        };
+
+        let date = Date::from_calendar_date(
+            number(0..4)? as i32,
+            Month::try_from(number(4..6)? as u8)?,
+            number(6..8)? as u8,
+        )?;
+
+        // milliseconds are ignored
+        let hhmmss = local.split('.').next().unwrap_or(local);
+        let (time, has_time) = match hhmmss.len() {
+            8 => (Time::MIDNIGHT, false),
+            12 => (
+                Time::from_hms(number(8..10)? as u8, number(10..12)? as u8, 0)?,
+                true,
+            ),
+            14 => (
+                Time::from_hms(
+                    number(8..10)? as u8,
+                    number(10..12)? as u8,
+                    number(12..14)? as u8,
+                )?,
+                true,
+            ),
+            _ => return Err(eyre!("invalid datetime {}", s)),
+        };
+
+        let offset = tz.map(parse_gmt_offset).transpose()?;
+
+        Ok(OfxDateTime {
+            datetime: PrimitiveDateTime::new(date, time),
+            has_time,
+            offset,
+        })
+    }
+}
+
+/// Parse the OFX gmt offset with optional tz name, e.g. `-7:MST`, `+5.30:IST`, or `0`.
+///
+/// The offset is in hours, and a fractional part of two digits is taken as minutes, of one digit as tenths of an hour.
+fn parse_gmt_offset(tz: &str) -> Result<UtcOffset> {
+    let invalid = || eyre!("invalid time zone offset {}", tz);
+    let offset = tz.split(':').next().unwrap_or(tz).trim();
+    let (sign, unsigned) = match offset.split_at_checked(1) {
+        Some(("-", unsigned)) => (-1, unsigned),
+        Some(("+", unsigned)) => (1, unsigned),
+        _ => (1, offset),
+    };
+    let (hours, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let hours = hours.parse::<i8>().map_err(|_| invalid())?;
+    let minutes = match fraction.len() {
+        0 => 0,
+        1 => fraction.parse::<i8>().map_err(|_| invalid())? * 6,
+        2 => fraction.parse::<i8>().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+    Ok(UtcOffset::from_hms(sign * hours, sign * minutes, 0)?)
+}
+
+impl OfxDateTime {
+    /// Convert into the target time zone, if the datetime has an explicit offset.
+    ///
+    /// Datetimes without an offset are taken to be already local to the target.
+    fn normalise(self, tz: Option<Tz>) -> Self {
+        match (tz, self.offset) {
+            (Some(tz), Some(offset)) => {
+                let datetime = self.datetime.assume_offset(offset);
+                let target = match tz {
+                    Tz::Local => UtcOffset::local_offset_at(datetime).unwrap_or_else(|e| {
+                        eprintln!(
+                            "warning: local time zone offset at {} cannot be determined ({}), keeping {}",
+                            datetime, e, offset
+                        );
+                        offset
+                    }),
+                    Tz::Offset(target) => target,
+                };
+                let datetime = datetime.to_offset(target);
+                OfxDateTime {
+                    datetime: PrimitiveDateTime::new(datetime.date(), datetime.time()),
+                    has_time: self.has_time,
+                    offset: Some(target),
+                }
+            }
+            _ => self,
+        }
+    }
+
+    fn yyyymmdd(&self) -> String {
+        let date = self.datetime.date();
+        format!(
+            "{:04}{:02}{:02}",
+            date.year(),
+            date.month() as u8,
+            date.day()
+        )
+    }
+
+    fn hhmmss(&self) -> Option<String> {
+        self.has_time.then(|| {
+            let time = self.datetime.time();
+            format!("{:02}{:02}{:02}", time.hour(), time.minute(), time.second())
+        })
+    }
+}
+
+/// Normalise all datetime fields, that is, those whose final dotted component starts with `dt`.
+///
+/// Each is replaced by its date as `yyyymmdd` in the target time zone, with the time of day if
+/// present as `<key>-time`, and the original string as `<key>-raw`.  Unparseable datetimes are
+/// simply truncated to their first 8 characters.
+pub(crate) fn normalise_fields(fields: &mut HashMap<String, String>, tz: Option<Tz>) {
+    let keys = fields
+        .keys()
+        .filter(|k| k.rsplit('.').next().unwrap_or(k).starts_with("dt"))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for key in keys {
+        let raw = fields.remove(&key).unwrap();
+        match raw.parse::<OfxDateTime>() {
+            Ok(datetime) => {
+                let datetime = datetime.normalise(tz);
+                if let Some(hhmmss) = datetime.hhmmss() {
+                    fields.insert(format!("{}{}", key, TIME_SUFFIX), hhmmss);
+                }
+                fields.insert(key.clone(), datetime.yyyymmdd());
+            }
+            Err(_) => {
+                fields.insert(key.clone(), raw.chars().take(8).collect());
+            }
+        }
+        fields.insert(format!("{}{}", key, RAW_SUFFIX), raw);
+    }
+}
@@ -0,0 +1,19 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Decode content in the declared encoding, if any, without any UTF-8 byte order mark.
+///
+/// Where no encoding is declared, or UTF-8 is declared but the content is not valid UTF-8,
+/// the content is taken to be Windows-1252, a superset of Latin-1, as commonly emitted by banks.
+pub(crate) fn decode<'a>(content: &'a [u8], encoding: Option<&'static Encoding>) -> Cow<'a, str> {
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+    match encoding {
+        Some(encoding) if encoding != UTF_8 => encoding.decode_without_bom_handling(content).0,
+        _ => match std::str::from_utf8(content) {
+            Ok(content) => Cow::Borrowed(content),
+            Err(_) => WINDOWS_1252.decode_without_bom_handling(content).0,
+        },
+    }
+}
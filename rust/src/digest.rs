@@ -1,7 +1,7 @@
 use beancount_parser_lima::{
     self as parser, BeancountParser, BeancountSources, ParseError, ParseSuccess, Spanned,
 };
-use color_eyre::eyre::{eyre, Result};
+use color_eyre::eyre::{Result, eyre};
 use serde::Serialize;
 use std::{collections::HashSet, io::Write, path::Path};
 
@@ -144,23 +144,22 @@ impl<'a> DigestBuilder<'a> {
         if let Some(accid) = directive
             .metadata()
             .key_value(parser::Key::try_from(self.accid_key.as_str()).unwrap())
+            && let parser::MetaValue::Simple(parser::SimpleValue::String(accid)) = accid.item()
         {
-            if let parser::MetaValue::Simple(parser::SimpleValue::String(accid)) = accid.item() {
-                use hashbrown::hash_map::Entry::*;
-                // ugh, borrow checker can't cope, so leak the string
-                let accid = accid.to_string().leak();
-                let account = open.account().item().as_ref();
-                match self.accids.entry(accid) {
-                    Occupied(entry) => {
-                        self.errors.push(directive.error(format!(
-                            "accid {} also used for {}",
-                            accid,
-                            entry.get()
-                        )));
-                    }
-                    Vacant(entry) => {
-                        entry.insert(account);
-                    }
+            use hashbrown::hash_map::Entry::*;
+            // ugh, borrow checker can't cope, so leak the string
+            let accid = accid.to_string().leak();
+            let account = open.account().item().as_ref();
+            match self.accids.entry(accid) {
+                Occupied(entry) => {
+                    self.errors.push(directive.error(format!(
+                        "accid {} also used for {}",
+                        accid,
+                        entry.get()
+                    )));
+                }
+                Vacant(entry) => {
+                    entry.insert(account);
                 }
             }
         }
@@ -179,13 +178,10 @@ impl<'a> DigestBuilder<'a> {
             if let Some(txnid) = directive
                 .metadata()
                 .key_value(parser::Key::try_from(txnid_key.as_str()).unwrap())
+                && let parser::MetaValue::Simple(parser::SimpleValue::String(txnid)) = txnid.item()
+                && !self.txnids.contains(*txnid)
             {
-                if let parser::MetaValue::Simple(parser::SimpleValue::String(txnid)) = txnid.item()
-                {
-                    if !self.txnids.contains(*txnid) {
-                        self.txnids.insert(txnid.to_string());
-                    }
-                }
+                self.txnids.insert(txnid.to_string());
             }
         }
 
@@ -198,35 +194,28 @@ impl<'a> DigestBuilder<'a> {
         if let Some(payee2) = directive
             .metadata()
             .key_value(parser::Key::try_from(self.payee2_key.as_str()).unwrap())
+            && let parser::MetaValue::Simple(parser::SimpleValue::String(payee2)) = *payee2.item()
         {
-            if let parser::MetaValue::Simple(parser::SimpleValue::String(payee2)) = *payee2.item() {
-                {
-                    // ugh, borrow checker can't cope, so leak the string
-                    count_accounts(
-                        &mut self.payees,
-                        payee2.to_string().leak(),
-                        primary_account.iter().copied(),
-                    );
-                }
-            }
+            // ugh, borrow checker can't cope, so leak the string
+            count_accounts(
+                &mut self.payees,
+                payee2.to_string().leak(),
+                primary_account.iter().copied(),
+            );
         }
 
         if let Some(narration2) = directive
             .metadata()
             .key_value(parser::Key::try_from(self.narration2_key.as_str()).unwrap())
-        {
-            if let parser::MetaValue::Simple(parser::SimpleValue::String(narration2)) =
+            && let parser::MetaValue::Simple(parser::SimpleValue::String(narration2)) =
                 *narration2.item()
-            {
-                {
-                    // ugh, borrow checker can't cope, so leak the string
-                    count_accounts(
-                        &mut self.narrations,
-                        narration2.to_string().leak(),
-                        primary_account.iter().copied(),
-                    );
-                }
-            }
+        {
+            // ugh, borrow checker can't cope, so leak the string
+            count_accounts(
+                &mut self.narrations,
+                narration2.to_string().leak(),
+                primary_account.iter().copied(),
+            );
         }
 
         // update payee and narration map to account name only for second and subsequent postings,
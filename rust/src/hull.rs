@@ -7,6 +7,9 @@ use std::collections::HashMap;
 pub struct Hull {
     pub hdr: HashMap<String, String>,
     pub txns: Vec<HashMap<String, String>>,
+    /// Holdings, such as from an OFX INVPOSLIST, omitted if none
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<HashMap<String, String>>,
 }
 
 #[derive(Serialize, Debug)]
@@ -0,0 +1,362 @@
+use color_eyre::eyre::{Result, eyre};
+use regex::Regex;
+use std::{collections::HashMap, path::Path, sync::LazyLock};
+use time::{Date, Month};
+
+use crate::hull::{Hull, Hulls};
+use crate::year::full_year;
+
+const ACCTID: &str = "acctid";
+const AMOUNT: &str = "amount";
+const CURRENCY: &str = "currency";
+const INFO: &str = "info";
+
+/// Prefix for the structured subfields of :86: information
+const INFO_PREFIX: &str = "info.";
+
+/// A tag, with its value continuing on any following lines up to the next tag.
+static TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^:([0-9]{2}[A-Z]?):(.*)$").unwrap());
+
+/// A balance, as in :60F:, :62F:, or :64:, for example `C250131EUR1234,56`
+static BALANCE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([CD])([0-9]{6})([A-Z]{3})([0-9]+,[0-9]*)$").unwrap());
+
+/// The first line of a :61: statement line, for example `2501310131DR123,45NTRFNONREF//B123`,
+/// comprising value date, optional entry date, debit/credit mark, optional funds code, amount,
+/// transaction type, customer reference, and optional bank reference.
+static STATEMENT_LINE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^([0-9]{6})([0-9]{4})?(R?[CD])([A-Z])?([0-9]+,[0-9]*)([NFS][A-Z0-9]{3})(.*?)(?://(.*))?$",
+    )
+    .unwrap()
+});
+
+/// The number and sum of entries in MT942, as in :90D: and :90C:, for example `5EUR1234,56`
+static ENTRIES_SUM_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([0-9]+)([A-Z]{3})([0-9]+,[0-9]*)$").unwrap());
+
+/// Structured :86: information, as used by German and Dutch banks, for example
+/// `166?00GUTSCHRIFT?20EREF+123?21SVWZ+Invoice 1?32ACME GMBH`, comprising a three digit
+/// transaction code, then subfields each introduced by a separator and two digit number.
+static STRUCTURED_INFO_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([0-9]{3})([^0-9A-Za-z ])[0-9]{2}").unwrap());
+
+/// Keywords within the purpose of SEPA transactions, for example `EREF+`
+static SEPA_KEYWORD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(EREF|KREF|MREF|CRED|DEBT|COAM|OAMT|SVWZ|ABWA|ABWE)\+").unwrap());
+
+/// Names of the structured :86: subfields, by number.  Purpose and name are continued over
+/// several subfields, which are concatenated.
+const INFO_SUBFIELDS: &[(&str, &str)] = &[
+    ("00", "posting-text"),
+    ("10", "primanota"),
+    ("30", "bic"),
+    ("31", "account"),
+    ("32", "name"),
+    ("33", "name"),
+    ("34", "text-key-extension"),
+];
+
+const INFO_PURPOSE_SUBFIELDS: &[&str] = &[
+    "20", "21", "22", "23", "24", "25", "26", "27", "28", "29", "60", "61", "62", "63",
+];
+
+struct Tag<'a> {
+    name: &'a str,
+    value: String,
+    line_number: usize,
+}
+
+/// Hull each statement in the MT940 or MT942 file, that is, from each :20: tag.
+pub(crate) fn parse(path: &Path, content: &str) -> Result<Hulls> {
+    let mut hulls = Vec::<Hull>::default();
+    let mut previous_tag_name = None;
+
+    for tag in tags(content) {
+        let invalid = |description: &str| {
+            eyre!(
+                "invalid {} {:?} at line {} in {}",
+                description,
+                tag.value,
+                tag.line_number,
+                path.to_string_lossy()
+            )
+        };
+
+        let follows_statement_line = previous_tag_name == Some("61");
+        previous_tag_name = Some(tag.name);
+
+        if tag.name == "20" {
+            hulls.push(Hull {
+                hdr: HashMap::from([
+                    ("message".to_string(), "MT940".to_string()),
+                    ("transaction-reference".to_string(), tag.value.clone()),
+                ]),
+                txns: Vec::default(),
+                positions: Vec::default(),
+            });
+            continue;
+        }
+
+        let Some(hull) = hulls.last_mut() else {
+            return Err(eyre!(
+                "tag :{}: before :20: at line {} in {}",
+                tag.name,
+                tag.line_number,
+                path.to_string_lossy()
+            ));
+        };
+        let hdr = &mut hull.hdr;
+
+        match tag.name {
+            "21" => {
+                hdr.insert("related-reference".to_string(), tag.value);
+            }
+            "25" => {
+                hdr.insert(ACCTID.to_string(), tag.value.clone());
+                hdr.insert("account".to_string(), tag.value);
+            }
+            "28C" => {
+                hdr.insert("statement-number".to_string(), tag.value);
+            }
+            "60F" | "60M" | "62F" | "62M" | "64" | "65" => {
+                let name = match tag.name {
+                    "60F" | "60M" => "opening-balance",
+                    "62F" | "62M" => "closing-balance",
+                    "64" => "closing-available-balance",
+                    _ => "forward-available-balance",
+                };
+                let (date, currency, amount) =
+                    balance(&tag.value).ok_or_else(|| invalid("balance"))?;
+                // forward available balances may be repeated for successive dates, of which the first is kept
+                if !hdr.contains_key(name) {
+                    hdr.insert(name.to_string(), amount);
+                    hdr.insert(format!("{}-date", name), date);
+                    hdr.entry(CURRENCY.to_string()).or_insert(currency);
+                    // as for a statement continued in another message
+                    if tag.name.ends_with('M') {
+                        hdr.insert(format!("{}-intermediate", name), "true".to_string());
+                    }
+                }
+            }
+            "13D" | "34F" | "90D" | "90C" => {
+                // tags only present in MT942 interim transaction reports
+                hdr.insert("message".to_string(), "MT942".to_string());
+                match tag.name {
+                    "13D" => {
+                        hdr.insert("datetime".to_string(), tag.value);
+                    }
+                    "34F" => {
+                        hdr.entry("floor-limit".to_string()).or_insert(tag.value);
+                    }
+                    _ => {
+                        let prefix = if tag.name == "90D" { "debit" } else { "credit" };
+                        let captures = ENTRIES_SUM_RE
+                            .captures(&tag.value)
+                            .ok_or_else(|| invalid("number and sum of entries"))?;
+                        hdr.insert(format!("{}-count", prefix), captures[1].to_string());
+                        hdr.insert(format!("{}-total", prefix), decimal(&captures[3]));
+                    }
+                }
+            }
+            "61" => {
+                let txn = statement_line(&tag.value).ok_or_else(|| invalid("statement line"))?;
+                hull.txns.push(txn);
+            }
+            "86" => {
+                // information follows the statement line to which it refers, or otherwise is for the statement as a whole
+                let fields = match hull.txns.last_mut() {
+                    Some(txn) if follows_statement_line => txn,
+                    _ => hdr,
+                };
+                info_fields(&tag.value, fields);
+            }
+            _ => (),
+        }
+    }
+
+    if hulls.is_empty() {
+        return Err(eyre!("no statements found in {:?}", path));
+    }
+
+    Ok(Hulls(hulls))
+}
+
+/// Split the content into tags, skipping any SWIFT block headers and message trailers.
+fn tags(content: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::<Tag>::default();
+    let mut in_tag = false;
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end();
+        if let Some(captures) = TAG_RE.captures(line) {
+            tags.push(Tag {
+                name: captures.get(1).unwrap().as_str(),
+                value: captures[2].to_string(),
+                line_number: i + 1,
+            });
+            in_tag = true;
+        } else if line.starts_with('{') || line == "-" || line.starts_with("-}") {
+            // the end of a message is a line of just `-`, or `-}` perhaps followed by the trailer block
+            in_tag = false;
+        } else if in_tag && let Some(tag) = tags.last_mut() {
+            tag.value.push('\n');
+            tag.value.push_str(line);
+        }
+    }
+
+    tags
+}
+
+/// Parse a `YYMMDD` date as `yyyyMMdd`, with the year taken as 20yy if less than 70, and otherwise 19yy.
+fn date(yymmdd: &str) -> Option<String> {
+    let year = full_year(yymmdd.get(0..2)?.parse::<i32>().ok()?);
+    let month = Month::try_from(yymmdd.get(2..4)?.parse::<u8>().ok()?).ok()?;
+    let date = Date::from_calendar_date(year, month, yymmdd.get(4..6)?.parse().ok()?).ok()?;
+    Some(yyyymmdd(date))
+}
+
+fn yyyymmdd(date: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
+
+/// Convert an amount with decimal comma, such as `1234,` or `1234,56`, to a decimal point.
+fn decimal(amount: &str) -> String {
+    let amount = amount.replace(',', ".");
+    amount
+        .strip_suffix('.')
+        .map(str::to_string)
+        .unwrap_or(amount)
+}
+
+/// The date, currency, and signed amount of a balance.
+fn balance(value: &str) -> Option<(String, String, String)> {
+    let captures = BALANCE_RE.captures(value)?;
+    let amount = decimal(&captures[4]);
+    let amount = if &captures[1] == "D" {
+        format!("-{}", amount)
+    } else {
+        amount
+    };
+    Some((date(&captures[2])?, captures[3].to_string(), amount))
+}
+
+fn statement_line(value: &str) -> Option<HashMap<String, String>> {
+    let (first_line, supplementary_details) = value
+        .split_once('\n')
+        .map_or((value, None), |(first, rest)| (first, Some(rest)));
+    let captures = STATEMENT_LINE_RE.captures(first_line)?;
+
+    let value_date = date(&captures[1])?;
+    let credit_debit = &captures[3];
+    let amount = decimal(&captures[5]);
+    // a reversal of a credit is a debit, and vice versa
+    let amount = if credit_debit == "D" || credit_debit == "RC" {
+        format!("-{}", amount)
+    } else {
+        amount
+    };
+
+    let mut txn = HashMap::from([
+        ("value-date".to_string(), value_date.clone()),
+        ("credit-debit".to_string(), credit_debit.to_string()),
+        (AMOUNT.to_string(), amount),
+        ("transaction-type".to_string(), captures[6].to_string()),
+    ]);
+
+    // the entry date has no year, so is taken as that nearest the value date
+    if let Some(mmdd) = captures.get(2) {
+        let value_year = value_date[0..4].parse::<i32>().ok()?;
+        let value_month = value_date[4..6].parse::<u8>().ok()?;
+        let entry_month = mmdd.as_str()[0..2].parse::<u8>().ok()?;
+        let entry_year = match (value_month, entry_month) {
+            (12, 1) => value_year + 1,
+            (1, 12) => value_year - 1,
+            _ => value_year,
+        };
+        let entry_date = date(&format!("{:02}{}", entry_year % 100, mmdd.as_str()))?;
+        txn.insert("entry-date".to_string(), entry_date);
+    }
+    if !captures[7].is_empty() {
+        txn.insert("customer-reference".to_string(), captures[7].to_string());
+    }
+    if let Some(funds_code) = captures.get(4) {
+        txn.insert("funds-code".to_string(), funds_code.as_str().to_string());
+    }
+    if let Some(bank_reference) = captures.get(8) {
+        txn.insert(
+            "bank-reference".to_string(),
+            bank_reference.as_str().to_string(),
+        );
+    }
+    if let Some(supplementary_details) = supplementary_details {
+        txn.insert(
+            "supplementary-details".to_string(),
+            supplementary_details.to_string(),
+        );
+    }
+
+    Some(txn)
+}
+
+/// Insert the :86: information raw, and if structured, also split into its subfields.
+fn info_fields(value: &str, fields: &mut HashMap<String, String>) {
+    fields.insert(INFO.to_string(), value.to_string());
+
+    let Some(captures) = STRUCTURED_INFO_RE.captures(value) else {
+        return;
+    };
+    let separator = captures[2].chars().next().unwrap();
+    fields.insert(
+        format!("{}transaction-code", INFO_PREFIX),
+        captures[1].to_string(),
+    );
+
+    // line breaks merely wrap the subfields
+    let value = value.replace('\n', "");
+    let mut purpose = String::default();
+    for subfield in value[3..].split(separator).skip(1) {
+        let Some((number, text)) = subfield
+            .get(0..2)
+            .filter(|number| number.chars().all(|c| c.is_ascii_digit()))
+            .map(|number| (number, &subfield[2..]))
+        else {
+            continue;
+        };
+
+        fields.insert(format!("{}{}", INFO_PREFIX, number), text.to_string());
+        if INFO_PURPOSE_SUBFIELDS.contains(&number) {
+            purpose.push_str(text);
+        } else if let Some((_, name)) = INFO_SUBFIELDS.iter().find(|(n, _)| *n == number) {
+            fields
+                .entry(format!("{}{}", INFO_PREFIX, name))
+                .and_modify(|field| field.push_str(text))
+                .or_insert_with(|| text.to_string());
+        }
+    }
+
+    if !purpose.is_empty() {
+        sepa_fields(&purpose, fields);
+        fields.insert(format!("{}purpose", INFO_PREFIX), purpose);
+    }
+}
+
+/// Insert the SEPA keyword fields of the purpose, for example `EREF+` as `info.eref`.
+fn sepa_fields(purpose: &str, fields: &mut HashMap<String, String>) {
+    let keywords = SEPA_KEYWORD_RE.captures_iter(purpose).collect::<Vec<_>>();
+    for (i, keyword) in keywords.iter().enumerate() {
+        let whole = keyword.get(0).unwrap();
+        let end = keywords
+            .get(i + 1)
+            .map_or(purpose.len(), |next| next.get(0).unwrap().start());
+        fields.insert(
+            format!("{}{}", INFO_PREFIX, keyword[1].to_lowercase()),
+            purpose[whole.end()..end].trim().to_string(),
+        );
+    }
+}
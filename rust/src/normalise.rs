@@ -0,0 +1,151 @@
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use time::Date;
+
+use crate::year::full_year;
+
+/// Parse a locale-formatted amount, such as `1.234,56`, `(12.00)`, `12.00-`, or `£12.00`.
+///
+/// Anything other than digits, the decimal separator, signs, and parentheses is ignored,
+/// including currency symbols and thousands separators.  Parentheses or a trailing minus
+/// denote a negative amount.
+pub(crate) fn parse_decimal(s: &str, decimal_separator: char) -> Option<Decimal> {
+    let s = s.trim();
+    let negative_by_parentheses = s.starts_with('(') && s.ends_with(')');
+    let negative_by_trailing_minus = s.ends_with('-');
+
+    let mut canonical = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '0'..='9' => canonical.push(c),
+            '-' if canonical.is_empty() => canonical.push(c),
+            c if c == decimal_separator => canonical.push('.'),
+            _ => (),
+        }
+    }
+
+    let decimal = Decimal::from_str(&canonical).ok()?;
+    if negative_by_parentheses || negative_by_trailing_minus {
+        Some(-decimal.abs())
+    } else {
+        Some(decimal)
+    }
+}
+
+/// A date format in the style used by realizers, e.g. `dd/MM/yyyy` or `d MMM yy`.
+///
+/// Supported fields are `d` and `dd` for day, `M` and `MM` for month number, `MMM` for English
+/// month abbreviation, and `yy` and `yyyy` for year, with two-digit years taken as 20yy if less
+/// than 70, and otherwise 19yy.
+/// Any other character must match literally.
+#[derive(Clone, Debug)]
+pub(crate) struct DateFormat(Vec<DateFormatItem>);
+
+#[derive(Clone, Debug)]
+enum DateFormatItem {
+    Day,
+    Month,
+    MonthAbbreviation,
+    Year2,
+    Year4,
+    Literal(char),
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use DateFormatItem::*;
+
+        let mut items = Vec::default();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let mut width = 1;
+            while chars.next_if_eq(&c).is_some() {
+                width += 1;
+            }
+            let item = match (c, width) {
+                ('d', 1 | 2) => Day,
+                ('M', 1 | 2) => Month,
+                ('M', 3) => MonthAbbreviation,
+                ('y', 2) => Year2,
+                ('y', 4) => Year4,
+                ('d' | 'M' | 'y', _) => {
+                    return Err(format!("unsupported date format field in {}", s));
+                }
+                _ => {
+                    items.extend(std::iter::repeat_n(Literal(c), width));
+                    continue;
+                }
+            };
+            items.push(item);
+        }
+        Ok(DateFormat(items))
+    }
+}
+
+impl DateFormat {
+    pub(crate) fn parse(&self, s: &str) -> Option<Date> {
+        use DateFormatItem::*;
+
+        let mut rest = s.trim();
+        let (mut day, mut month, mut year) = (None, None, None);
+
+        // consume up to `max` digits
+        fn digits(rest: &mut &str, max: usize) -> Option<i32> {
+            let n = rest
+                .char_indices()
+                .take(max)
+                .take_while(|(_, c)| c.is_ascii_digit())
+                .count();
+            let (number, remainder) = rest.split_at(n);
+            *rest = remainder;
+            number.parse().ok()
+        }
+
+        for item in self.0.iter() {
+            match item {
+                Day => day = Some(digits(&mut rest, 2)?),
+                Month => month = Some(digits(&mut rest, 2)?),
+                MonthAbbreviation => {
+                    let abbreviation = rest.get(..3)?;
+                    month = Some(
+                        MONTH_ABBREVIATIONS
+                            .iter()
+                            .position(|m| m.eq_ignore_ascii_case(abbreviation))?
+                            as i32
+                            + 1,
+                    );
+                    rest = &rest[3..];
+                }
+                Year2 => year = Some(full_year(digits(&mut rest, 2)?)),
+                Year4 => year = Some(digits(&mut rest, 4)?),
+                Literal(c) => rest = rest.strip_prefix(*c)?,
+            }
+        }
+
+        if !rest.is_empty() {
+            return None;
+        }
+        Date::from_calendar_date(
+            year?,
+            time::Month::try_from(u8::try_from(month?).ok()?).ok()?,
+            u8::try_from(day?).ok()?,
+        )
+        .ok()
+    }
+}
+
+const MONTH_ABBREVIATIONS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format a date as ISO 8601, `yyyy-MM-dd`.
+pub(crate) fn iso_date(date: Date) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
@@ -0,0 +1,600 @@
+use color_eyre::eyre::{Result, eyre};
+use rust_decimal::Decimal;
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use slugify::slugify;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::Path,
+    str::FromStr,
+};
+
+use super::{
+    ACCTID, BALAMT, BROKERID, CORRECTACTION, CORRECTFITID, CURRATE, CURRENCY, DELETE, DELETED,
+    DTASOF, DTEND, DTPOSTED, DTSTART, DTTRADE, FITID, INVPOSTYPE, INVTRNTYPE, MSGSET, ORIGAMT,
+    ORIGCUR, ORIGCURRENCY, PAYEE, PAYEE_NAME, SECID_UNIQUEID, SECINFOTYPE, SECNAME, TICKER, TOTAL,
+    TRNAMT,
+};
+use crate::hull::{Hull, Hulls};
+
+/// An OFX aggregate, that is, an element containing other elements rather than text.
+///
+/// Both OFX1 SGML and OFX2 XML are deserialized into this generic form, so that any field is
+/// available without modelling it in Rust, and both versions are hulled in the same way.
+/// Element names are normalised to lowercase, and order and repetition are preserved.
+#[derive(Default, Debug)]
+pub(crate) struct Aggregate(Vec<(String, Element)>);
+
+#[derive(Debug)]
+pub(crate) enum Element {
+    Text(String),
+    Aggregate(Aggregate),
+}
+
+impl Aggregate {
+    pub(crate) fn children(&self) -> impl Iterator<Item = (&str, &Element)> {
+        self.0
+            .iter()
+            .map(|(name, element)| (name.as_str(), element))
+    }
+
+    pub(crate) fn aggregate(&self, name: &str) -> Option<&Aggregate> {
+        self.aggregates(name).next()
+    }
+
+    pub(crate) fn aggregates(&self, name: &str) -> impl Iterator<Item = &Aggregate> {
+        self.children()
+            .filter_map(move |(n, element)| match element {
+                Element::Aggregate(aggregate) if n == name => Some(aggregate),
+                _ => None,
+            })
+    }
+
+    pub(crate) fn text(&self, name: &str) -> Option<&str> {
+        self.children().find_map(|(n, element)| match element {
+            Element::Text(text) if n == name => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The key by which an item of a list is distinguished from its siblings, if it is such an item.
+    fn list_key(&self, name: &str) -> Option<String> {
+        KEYED_ITEMS
+            .iter()
+            .find_map(|(item, key)| (*item == name).then(|| self.text(key)).flatten())
+            .map(|key| slugify(key, "", "-", None))
+    }
+
+    /// Flatten all text elements into `fields`, with nested aggregates as dotted keys.
+    ///
+    /// Aggregates named in `transparent` contribute their elements without a prefix, and
+    /// list items such as BAL are prefixed by their slugified key rather than their name.
+    pub(crate) fn flatten_into(
+        &self,
+        prefix: &str,
+        transparent: &[&str],
+        fields: &mut HashMap<String, String>,
+    ) {
+        for (name, element) in self.children() {
+            match element {
+                Element::Text(text) => {
+                    fields.insert(format!("{}{}", prefix, name), text.clone());
+                }
+                Element::Aggregate(aggregate) if transparent.contains(&name) => {
+                    aggregate.flatten_into(prefix, transparent, fields)
+                }
+                Element::Aggregate(aggregate) => {
+                    if let Some(key) = aggregate.list_key(name) {
+                        aggregate.flatten_into(&format!("{}{}.", prefix, key), transparent, fields)
+                    } else {
+                        aggregate.flatten_into(&format!("{}{}.", prefix, name), transparent, fields)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Element {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ElementVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Aggregate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // not deserialize_any, as quick-xml needs to know to look for the root element
+        match deserializer.deserialize_map(ElementVisitor)? {
+            Element::Aggregate(aggregate) => Ok(aggregate),
+            Element::Text(text) => Err(serde::de::Error::custom(format!(
+                "expected aggregate, found text {:?}",
+                text
+            ))),
+        }
+    }
+}
+
+struct ElementVisitor;
+
+impl<'de> Visitor<'de> for ElementVisitor {
+    type Value = Element;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an OFX element or aggregate")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Element::Text(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Element::Text(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(Element::Text(String::default()))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut children = Vec::default();
+        while let Some((name, element)) = map.next_entry::<String, Element>()? {
+            children.push((name.to_lowercase(), element));
+        }
+
+        // quick-xml presents the content of a text-only element as a map with a single `$text` entry
+        match children.as_slice() {
+            [(name, Element::Text(_))] if name == "$text" => Ok(children.pop().unwrap().1),
+            _ => Ok(Element::Aggregate(Aggregate(children))),
+        }
+    }
+}
+
+/// Fields of a bank, credit card, or loan transaction, with nested aggregates such as `CURRENCY` as dotted keys.
+fn stmttrn_fields(stmttrn: &Aggregate, transparent: &[&str]) -> HashMap<String, String> {
+    let mut fields = HashMap::default();
+    stmttrn.flatten_into("", transparent, &mut fields);
+
+    // the payee name is also provided undotted, as it always was
+    if let Some(name) = fields.get(PAYEE_NAME).cloned() {
+        fields.insert(PAYEE.to_string(), name);
+    }
+
+    fields
+}
+
+/// Aggregates within loan transactions which are merely structural, so that the principal,
+/// interest, insurance, and escrow amounts are fields such as `prinamt` and `escrwtotalamt`.
+const LOANSTMTTRN_TRANSPARENT: &[&str] = &["loantrnamt", "escrwamt"];
+
+/// Aggregates within investment transactions which are merely structural, and flattened without prefix.
+const INVTRAN_TRANSPARENT: &[&str] = &["invbuy", "invsell", "invtran", "stmttrn"];
+
+/// Aggregates within positions which are merely structural, and flattened without prefix.
+const INVPOS_TRANSPARENT: &[&str] = &["invpos"];
+
+/// Aggregates within securities which are merely structural, and flattened without prefix.
+const SECINFO_TRANSPARENT: &[&str] = &["secinfo"];
+
+/// Securities from SECLISTMSGSRSV1, by unique ID.
+#[derive(Default, Debug)]
+struct SecList(BTreeMap<String, HashMap<String, String>>);
+
+impl SecList {
+    fn new(seclistmsgsrsv1: Option<&Aggregate>) -> Self {
+        SecList(
+            seclistmsgsrsv1
+                .iter()
+                .flat_map(|seclistmsgsrsv1| seclistmsgsrsv1.aggregates("seclist"))
+                .flat_map(|seclist| seclist.children())
+                .filter_map(|(name, element)| match element {
+                    Element::Aggregate(info) => {
+                        let mut fields = HashMap::default();
+                        fields.insert(SECINFOTYPE.to_string(), name.to_uppercase());
+                        info.flatten_into("", SECINFO_TRANSPARENT, &mut fields);
+                        fields
+                            .get(SECID_UNIQUEID)
+                            .cloned()
+                            .map(|uniqueid| (uniqueid, fields))
+                    }
+                    Element::Text(_) => None,
+                })
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    /// Header fields for all securities, keyed by `seclist.<uniqueid>.<field>`.
+    fn hdr_fields(&self) -> impl Iterator<Item = (String, String)> {
+        self.0.iter().flat_map(|(uniqueid, fields)| {
+            fields
+                .iter()
+                .map(move |(k, v)| (format!("seclist.{}.{}", uniqueid, k), v.clone()))
+        })
+    }
+
+    /// Add ticker and security name for the security referenced by `fields`, if known.
+    fn resolve(&self, fields: &mut HashMap<String, String>) {
+        if let Some(security) = fields
+            .get(SECID_UNIQUEID)
+            .and_then(|uniqueid| self.0.get(uniqueid))
+        {
+            for key in [TICKER, SECNAME] {
+                if let Some(value) = security.get(key) {
+                    fields
+                        .entry(key.to_string())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Repeated list items, with the element whose value distinguishes each, e.g. `ballist.credit-limit.value`.
+const KEYED_ITEMS: &[(&str, &str)] = &[("bal", "name")];
+
+/// Statement aggregates, each of which is hulled separately.
+const STMTS: &[&str] = &["stmtrs", "ccstmtrs", "invstmtrs", "loanstmtrs"];
+
+/// Aggregates listing transactions, whose own text elements such as DTSTART are header fields.
+const TRANLISTS: &[&str] = &["banktranlist", "invtranlist", "loantranlist"];
+
+/// Aggregates listing positions.
+const POSLISTS: &[&str] = &["invposlist"];
+
+/// Header fields which are also provided undotted, as they always were, and the keys they are taken from.
+const HDR_ALIASES: &[(&str, &[&str])] = &[
+    (
+        ACCTID,
+        &[
+            "bankacctfrom.acctid",
+            "ccacctfrom.acctid",
+            "invacctfrom.acctid",
+            "loanacctfrom.loanacctid",
+        ],
+    ),
+    (BROKERID, &["invacctfrom.brokerid"]),
+    (BALAMT, &["ledgerbal.balamt"]),
+    (DTASOF, &["ledgerbal.dtasof"]),
+    (
+        DTSTART,
+        &[
+            "banktranlist.dtstart",
+            "invtranlist.dtstart",
+            "loantranlist.dtstart",
+        ],
+    ),
+    (
+        DTEND,
+        &[
+            "banktranlist.dtend",
+            "invtranlist.dtend",
+            "loantranlist.dtend",
+        ],
+    ),
+];
+
+/// Hull each statement in the OFX document, whatever its message set.
+///
+/// The OFX header fields, with lowercase names, are included in each hull header.
+pub(crate) fn hulls(path: &Path, ofx: &Aggregate, header: &[(String, String)]) -> Result<Hulls> {
+    let seclist = SecList::new(ofx.aggregate("seclistmsgsrsv1"));
+
+    let mut stmts = Vec::default();
+    for (msgset, msgsrs) in ofx
+        .children()
+        .filter_map(|(msgset, element)| match element {
+            Element::Aggregate(msgsrs) if msgset.ends_with("msgsrsv1") => Some((msgset, msgsrs)),
+            _ => None,
+        })
+    {
+        // each transaction wrapper, or the signon response, has its own status
+        for (trnrs_name, trnrs) in msgsrs
+            .children()
+            .filter_map(|(name, element)| match element {
+                Element::Aggregate(trnrs) => Some((name, trnrs)),
+                Element::Text(_) => None,
+            })
+        {
+            check_status(path, trnrs_name, trnrs)?;
+
+            stmts.extend(
+                trnrs
+                    .children()
+                    .filter_map(|(name, element)| match element {
+                        Element::Aggregate(stmtrs) if STMTS.contains(&name) => {
+                            Some((msgset, name, stmtrs))
+                        }
+                        _ => None,
+                    }),
+            );
+        }
+    }
+
+    if stmts.is_empty() {
+        return Err(eyre!("no statements found in OFX document {:?}", path));
+    }
+
+    // signon fields such as `fi.org` and `dtserver` identify the institution
+    let mut signon = HashMap::default();
+    if let Some(sonrs) = ofx
+        .aggregate("signonmsgsrsv1")
+        .and_then(|signonmsgsrsv1| signonmsgsrsv1.aggregate("sonrs"))
+    {
+        sonrs.flatten_into("", &[], &mut signon);
+        signon.retain(|k, _| !k.starts_with("status."));
+    }
+    let header = header.iter().cloned().chain(signon).collect::<Vec<_>>();
+
+    Ok(Hulls(
+        stmts
+            .into_iter()
+            .map(|(msgset, name, stmtrs)| stmt_hull(path, msgset, name, stmtrs, &seclist, &header))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Check the STATUS within `parent`, if it has a non-zero code.
+///
+/// Only a SEVERITY of ERROR, or none, is a failure, with the institution's message.  INFO and
+/// WARN accompany a valid response, so are merely warned of.
+fn check_status(path: &Path, parent_name: &str, parent: &Aggregate) -> Result<()> {
+    let Some(status) = parent.aggregate("status") else {
+        return Ok(());
+    };
+
+    match status.text("code") {
+        Some(code) if code.trim() != "0" => {
+            let severity = status.text("severity").unwrap_or("ERROR").trim();
+            let message = status
+                .text("message")
+                .or_else(|| status_description(code.trim()))
+                .unwrap_or("no message");
+            let report = format!(
+                "OFX document {:?} reports {} {} in {}: {}",
+                path,
+                severity,
+                code,
+                parent_name.to_uppercase(),
+                message
+            );
+            if severity.eq_ignore_ascii_case("ERROR") {
+                Err(eyre!(report))
+            } else {
+                eprintln!("warning: {}", report);
+                Ok(())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Description of common OFX status codes, for when the institution gives no message.
+fn status_description(code: &str) -> Option<&'static str> {
+    match code {
+        "2000" => Some("General error"),
+        "2003" => Some("Account not found"),
+        "15000" => Some("Must change USERPASS"),
+        "15500" => Some("Signon invalid"),
+        "15501" => Some("Customer account already in use"),
+        "15502" => Some("USERPASS lockout"),
+        _ => None,
+    }
+}
+
+fn stmt_hull(
+    path: &Path,
+    msgset: &str,
+    name: &str,
+    stmtrs: &Aggregate,
+    seclist: &SecList,
+    header: &[(String, String)],
+) -> Hull {
+    let mut hdr = header
+        .iter()
+        .cloned()
+        .chain(std::iter::once((MSGSET.to_string(), msgset.to_string())))
+        .collect::<HashMap<_, _>>();
+    let mut txns = Vec::default();
+    let mut positions = Vec::default();
+
+    for (child_name, element) in stmtrs.children() {
+        match element {
+            Element::Aggregate(tranlist) if TRANLISTS.contains(&child_name) => {
+                for (item_name, item) in tranlist.children() {
+                    match item {
+                        Element::Aggregate(txn) if item_name == "stmttrn" => {
+                            txns.push(stmttrn_fields(txn, &[]))
+                        }
+                        Element::Aggregate(txn) if item_name == "loanstmttrn" => {
+                            txns.push(stmttrn_fields(txn, LOANSTMTTRN_TRANSPARENT))
+                        }
+                        Element::Aggregate(txn) => {
+                            let mut fields = typed_fields(
+                                INVTRNTYPE,
+                                item_name,
+                                txn,
+                                INVTRAN_TRANSPARENT,
+                                seclist,
+                            );
+                            cash_fields(&mut fields);
+                            txns.push(fields)
+                        }
+                        Element::Text(text) => {
+                            hdr.insert(format!("{}.{}", child_name, item_name), text.clone());
+                        }
+                    }
+                }
+            }
+            Element::Aggregate(poslist) if POSLISTS.contains(&child_name) => {
+                for (item_name, item) in poslist.children() {
+                    if let Element::Aggregate(position) = item {
+                        positions.push(typed_fields(
+                            INVPOSTYPE,
+                            item_name,
+                            position,
+                            INVPOS_TRANSPARENT,
+                            seclist,
+                        ))
+                    }
+                }
+            }
+            // an empty list
+            Element::Text(_)
+                if TRANLISTS.contains(&child_name) || POSLISTS.contains(&child_name) => {}
+            Element::Aggregate(aggregate) => {
+                aggregate.flatten_into(&format!("{}.", child_name), &[], &mut hdr)
+            }
+            Element::Text(text) => {
+                hdr.insert(child_name.to_string(), text.clone());
+            }
+        }
+    }
+
+    for (alias, keys) in HDR_ALIASES {
+        if let Some(value) = keys.iter().find_map(|key| hdr.get(*key)) {
+            hdr.insert(alias.to_string(), value.clone());
+        }
+    }
+
+    if name == "invstmtrs" {
+        hdr.extend(seclist.hdr_fields());
+    }
+
+    for (i, fitid) in apply_corrections(path, &mut txns).into_iter().enumerate() {
+        hdr.insert(format!("{}.{}.{}", DELETED, i + 1, FITID), fitid);
+    }
+    txns.iter_mut().for_each(original_currency_fields);
+
+    Hull {
+        hdr,
+        txns,
+        positions,
+    }
+}
+
+/// Apply corrections to transactions in the same statement, returning the FITIDs of any others deleted.
+///
+/// A transaction with CORRECTACTION of REPLACE or DELETE supersedes the one whose FITID is its
+/// CORRECTFITID.  An applied DELETE is itself dropped, and an applied REPLACE is then an ordinary
+/// transaction, so loses `correctaction` and `correctfitid`.  A REPLACE of a transaction not in
+/// the statement, presumably imported previously, is retained with these for the realizer to
+/// act on, whereas such a DELETE is dropped, with its CORRECTFITID returned for the header.
+fn apply_corrections(path: &Path, txns: &mut Vec<HashMap<String, String>>) -> Vec<String> {
+    let mut superseded = HashSet::new();
+    let mut applied = HashSet::new();
+    let mut deleted = Vec::default();
+
+    for (i, correction) in txns.iter().enumerate() {
+        let (Some(action), Some(correctfitid)) =
+            (correction.get(CORRECTACTION), correction.get(CORRECTFITID))
+        else {
+            continue;
+        };
+        for (j, txn) in txns.iter().enumerate() {
+            if j != i && txn.get(FITID) == Some(correctfitid) {
+                superseded.insert(j);
+                applied.insert(i);
+            }
+        }
+        if action == DELETE && !applied.contains(&i) {
+            eprintln!(
+                "warning: DELETE of transaction {} not in statement in {}, which must be deleted if previously imported",
+                correctfitid,
+                path.to_string_lossy()
+            );
+            deleted.push(correctfitid.clone());
+        }
+    }
+
+    *txns = std::mem::take(txns)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, mut txn)| {
+            if superseded.contains(&i) || txn.get(CORRECTACTION).is_some_and(|a| a == DELETE) {
+                None
+            } else {
+                if applied.contains(&i) {
+                    txn.remove(CORRECTACTION);
+                    txn.remove(CORRECTFITID);
+                }
+                Some(txn)
+            }
+        })
+        .collect();
+
+    deleted
+}
+
+/// Add the original currency, amount, and rate of a transaction in a currency other than CURDEF.
+///
+/// With ORIGCURRENCY, the amount is in CURDEF and the original amount is computed from the rate,
+/// whereas with CURRENCY the amount is already in the original currency.  Either way, the
+/// original amount at the rate `currate` gives the amount in CURDEF.
+fn original_currency_fields(fields: &mut HashMap<String, String>) {
+    let Some((aggregate, cursym, currate)) =
+        [CURRENCY, ORIGCURRENCY].iter().find_map(|aggregate| {
+            let cursym = fields.get(&format!("{}.cursym", aggregate))?;
+            let currate = fields.get(&format!("{}.currate", aggregate))?;
+            Some((*aggregate, cursym.clone(), currate.clone()))
+        })
+    else {
+        return;
+    };
+    let Some(amount) = [TRNAMT, TOTAL].iter().find_map(|key| fields.get(*key)) else {
+        return;
+    };
+
+    // some institutions use a decimal comma
+    let decimal = |s: &str| Decimal::from_str(&s.trim().replace(',', ".")).ok();
+    let origamt = match (decimal(amount), decimal(&currate)) {
+        (Some(amount), Some(rate)) if aggregate == ORIGCURRENCY && !rate.is_zero() => {
+            let mut origamt = amount / rate;
+            origamt.rescale(amount.scale());
+            origamt
+        }
+        (Some(amount), Some(_)) => amount,
+        _ => return,
+    };
+
+    fields.insert(ORIGCUR.to_string(), cursym);
+    fields.insert(ORIGAMT.to_string(), origamt.to_string());
+    fields.insert(CURRATE.to_string(), currate);
+}
+
+/// Provide an investment transaction's effect on cash as `dtposted` and `trnamt`, as for a bank transaction.
+///
+/// These are taken from `dttrade` and `total`, except for INVBANKTRAN which has them already,
+/// and a transaction with no total, such as TRANSFER, has no effect on cash.
+fn cash_fields(fields: &mut HashMap<String, String>) {
+    if let Some(dttrade) = fields.get(DTTRADE).cloned() {
+        fields.entry(DTPOSTED.to_string()).or_insert(dttrade);
+    }
+    let total = fields
+        .get(TOTAL)
+        .cloned()
+        .unwrap_or_else(|| "0".to_string());
+    fields.entry(TRNAMT.to_string()).or_insert(total);
+}
+
+/// Flatten an aggregate whose type is given by its name, resolving any security it references.
+fn typed_fields(
+    type_key: &str,
+    name: &str,
+    aggregate: &Aggregate,
+    transparent: &[&str],
+    seclist: &SecList,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::default();
+    fields.insert(type_key.to_string(), name.to_uppercase());
+    aggregate.flatten_into("", transparent, &mut fields);
+    seclist.resolve(&mut fields);
+    fields
+}
@@ -1,183 +1,427 @@
-use color_eyre::eyre::{Result, WrapErr, eyre};
-use serde::Deserialize;
-use std::{collections::HashMap, path::Path};
-
-use super::{
-    ACCTID, BALAMT, CURDEF, DTASOF, DTPOSTED, FITID, MEMO, NAME, OFXHEADER, TRNAMT, TRNTYPE,
-    VERSION, truncate_yyyymmdd,
+use color_eyre::eyre::{Result, WrapErr};
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+    sync::LazyLock,
 };
-use crate::hull::{Hull, Hulls};
 
-#[derive(Deserialize, Debug)]
-struct Document {
-    bankmsgsrsv1: Option<BankMsgsRsV1>,
-    creditcardmsgsrsv1: Option<CreditCardMsgsRsV1>,
-}
+use super::{CHARSET, ENCODING};
+use crate::decode::decode;
+use crate::hull::Hulls;
+use crate::ofx::{self, Aggregate};
+
+/// Parse OFX1, where this document is preceded in the file by `preceding_lines` lines.
+///
+/// Where parsing fails, any malformation is reported with its location, or with `lenient`,
+/// common malformations are repaired with a warning for each, and parsing is retried.
+pub(crate) fn parse(
+    path: &Path,
+    preceding_lines: usize,
+    ofx_header: &[u8],
+    ofx_content: &[u8],
+    lenient: bool,
+) -> Result<Hulls> {
+    let header = parse_header(ofx_header);
+    // pad with the lines before the body, so that line numbers in parse errors are those of the file
+    let ofx_content = "\n"
+        .repeat(preceding_lines + ofx_header.iter().filter(|b| **b == b'\n').count())
+        + &decode(ofx_content, encoding(&header));
+
+    let e = match deserialize(path, &ofx_content).and_then(|ofx| ofx::hulls(path, &ofx, &header)) {
+        Ok(hulls) => return Ok(hulls),
+        Err(e) => e,
+    };
+    let repaired = Repaired::new(&ofx_content);
+    let Some(malformation) = repaired.malformations.first() else {
+        return Err(e);
+    };
+    if !lenient {
+        return Err(e.wrap_err(format!(
+            "Malformed OFX1 in {} at {}, try --lenient\n{}",
+            path.to_string_lossy(),
+            malformation,
+            malformation.snippet()
+        )));
+    }
 
-#[derive(Deserialize, Debug)]
-struct BankMsgsRsV1 {
-    stmttrnrs: StmtTrnRs,
+    for malformation in repaired.malformations.iter() {
+        eprintln!(
+            "warning: repaired malformed OFX1 in {} at {}",
+            path.to_string_lossy(),
+            malformation
+        );
+    }
+    deserialize(path, &repaired.content)
+        .and_then(|ofx| ofx::hulls(path, &ofx, &header))
+        .map_err(|e| {
+            e.wrap_err(format!(
+                "Malformed OFX1 in {} at {}, not recovered by --lenient\n{}",
+                path.to_string_lossy(),
+                malformation,
+                malformation.snippet()
+            ))
+        })
 }
 
-#[derive(Deserialize, Debug)]
-struct CreditCardMsgsRsV1 {
-    ccstmttrnrs: CcStmtTrnRs,
+fn deserialize(path: &Path, ofx_content: &str) -> Result<Aggregate> {
+    let sgml = sgmlish::Parser::builder()
+        .lowercase_names()
+        .expand_entities(expand_entity)
+        .parse(ofx_content)
+        .wrap_err_with(|| format!("Failed to parse OFX1 in {}", path.to_string_lossy()))?;
+    let sgml = sgmlish::transforms::normalize_end_tags(sgml).wrap_err_with(|| {
+        format!(
+            "Failed to normalize OFX1 end tags in {}",
+            path.to_string_lossy()
+        )
+    })?;
+    sgmlish::from_fragment::<Aggregate>(sgml)
+        .wrap_err_with(|| format!("Failed to deserialize OFX1 in {}", path.to_string_lossy()))
 }
 
-#[derive(Deserialize, Debug)]
-struct StmtTrnRs {
-    stmtrs: StmtRs,
+static HEADER_FIELD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"([A-Za-z]+)[ \t]*:[ \t]*(\S*)").unwrap());
+
+/// Parse the OFX1 header fields, `NAME:VALUE` separated by whitespace, with names lowercased.
+fn parse_header(ofx_header: &[u8]) -> Vec<(String, String)> {
+    HEADER_FIELD_RE
+        .captures_iter(&String::from_utf8_lossy(ofx_header))
+        .map(|captures| (captures[1].to_lowercase(), captures[2].to_string()))
+        .collect()
 }
 
-#[derive(Deserialize, Debug)]
-struct CcStmtTrnRs {
-    ccstmtrs: CcStmtRs,
+/// The encoding declared by the OFX1 header, where the character set may be given as a
+/// Windows code page number, e.g. `1252`.
+fn encoding(header: &[(String, String)]) -> Option<&'static Encoding> {
+    let field = |name: &str| {
+        header
+            .iter()
+            .find_map(|(k, v)| (k == name).then_some(v.as_str()))
+    };
+
+    match (field(ENCODING), field(CHARSET)) {
+        (Some("UTF-8" | "UNICODE"), _) => Some(UTF_8),
+        (_, Some(charset)) => Encoding::for_label(charset.as_bytes())
+            .or_else(|| Encoding::for_label(format!("windows-{}", charset).as_bytes())),
+        _ => None,
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct StmtRs {
-    curdef: String,
-    bankacctfrom: BankAcctFrom,
-    banktranlist: BankTranList,
-    ledgerbal: LedgerBal,
+/// Entities defined for OFX1 content, beyond character references which are always expanded.
+///
+/// Although OFX defines only the first few, banks also write HTML entities such as `&eacute;`,
+/// so the HTML Latin-1 entities and common punctuation such as `&ndash;` and `&euro;` are
+/// expanded too.  Other HTML entities are unknown, so rejected, or escaped with `--lenient`.
+fn expand_entity(entity: &str) -> Option<&'static str> {
+    match entity {
+        "lt" => Some("<"),
+        "gt" => Some(">"),
+        "amp" => Some("&"),
+        "nbsp" => Some(" "),
+        "quot" => Some("\""),
+        "apos" => Some("'"),
+        _ => HTML_ENTITIES
+            .iter()
+            .find_map(|(name, expansion)| (*name == entity).then_some(*expansion)),
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct CcStmtRs {
-    curdef: String,
-    ccacctfrom: CcAcctFrom,
-    banktranlist: BankTranList,
-    ledgerbal: LedgerBal,
+/// HTML entities, by name, which are case sensitive.
+const HTML_ENTITIES: &[(&str, &str)] = &[
+    ("iexcl", "¡"),
+    ("cent", "¢"),
+    ("pound", "£"),
+    ("curren", "¤"),
+    ("yen", "¥"),
+    ("brvbar", "¦"),
+    ("sect", "§"),
+    ("uml", "¨"),
+    ("copy", "©"),
+    ("ordf", "ª"),
+    ("laquo", "«"),
+    ("not", "¬"),
+    ("shy", "\u{AD}"),
+    ("reg", "®"),
+    ("macr", "¯"),
+    ("deg", "°"),
+    ("plusmn", "±"),
+    ("sup2", "²"),
+    ("sup3", "³"),
+    ("acute", "´"),
+    ("micro", "µ"),
+    ("para", "¶"),
+    ("middot", "·"),
+    ("cedil", "¸"),
+    ("sup1", "¹"),
+    ("ordm", "º"),
+    ("raquo", "»"),
+    ("frac14", "¼"),
+    ("frac12", "½"),
+    ("frac34", "¾"),
+    ("iquest", "¿"),
+    ("Agrave", "À"),
+    ("Aacute", "Á"),
+    ("Acirc", "Â"),
+    ("Atilde", "Ã"),
+    ("Auml", "Ä"),
+    ("Aring", "Å"),
+    ("AElig", "Æ"),
+    ("Ccedil", "Ç"),
+    ("Egrave", "È"),
+    ("Eacute", "É"),
+    ("Ecirc", "Ê"),
+    ("Euml", "Ë"),
+    ("Igrave", "Ì"),
+    ("Iacute", "Í"),
+    ("Icirc", "Î"),
+    ("Iuml", "Ï"),
+    ("ETH", "Ð"),
+    ("Ntilde", "Ñ"),
+    ("Ograve", "Ò"),
+    ("Oacute", "Ó"),
+    ("Ocirc", "Ô"),
+    ("Otilde", "Õ"),
+    ("Ouml", "Ö"),
+    ("times", "×"),
+    ("Oslash", "Ø"),
+    ("Ugrave", "Ù"),
+    ("Uacute", "Ú"),
+    ("Ucirc", "Û"),
+    ("Uuml", "Ü"),
+    ("Yacute", "Ý"),
+    ("THORN", "Þ"),
+    ("szlig", "ß"),
+    ("agrave", "à"),
+    ("aacute", "á"),
+    ("acirc", "â"),
+    ("atilde", "ã"),
+    ("auml", "ä"),
+    ("aring", "å"),
+    ("aelig", "æ"),
+    ("ccedil", "ç"),
+    ("egrave", "è"),
+    ("eacute", "é"),
+    ("ecirc", "ê"),
+    ("euml", "ë"),
+    ("igrave", "ì"),
+    ("iacute", "í"),
+    ("icirc", "î"),
+    ("iuml", "ï"),
+    ("eth", "ð"),
+    ("ntilde", "ñ"),
+    ("ograve", "ò"),
+    ("oacute", "ó"),
+    ("ocirc", "ô"),
+    ("otilde", "õ"),
+    ("ouml", "ö"),
+    ("divide", "÷"),
+    ("oslash", "ø"),
+    ("ugrave", "ù"),
+    ("uacute", "ú"),
+    ("ucirc", "û"),
+    ("uuml", "ü"),
+    ("yacute", "ý"),
+    ("thorn", "þ"),
+    ("yuml", "ÿ"),
+    ("OElig", "Œ"),
+    ("oelig", "œ"),
+    ("Scaron", "Š"),
+    ("scaron", "š"),
+    ("Yuml", "Ÿ"),
+    ("fnof", "ƒ"),
+    ("circ", "ˆ"),
+    ("tilde", "˜"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("lsquo", "‘"),
+    ("rsquo", "’"),
+    ("sbquo", "‚"),
+    ("ldquo", "“"),
+    ("rdquo", "”"),
+    ("bdquo", "„"),
+    ("dagger", "†"),
+    ("Dagger", "‡"),
+    ("bull", "•"),
+    ("hellip", "…"),
+    ("permil", "‰"),
+    ("lsaquo", "‹"),
+    ("rsaquo", "›"),
+    ("euro", "€"),
+    ("trade", "™"),
+];
+
+static ENTITY_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^&(#[0-9]+|#[xX][0-9A-Fa-f]+|[A-Za-z][A-Za-z0-9.-]*)").unwrap());
+
+/// A malformation found in OFX1 content, located by line and column in the file.
+#[derive(Debug)]
+struct Malformation {
+    line: usize,
+    column: usize,
+    message: String,
+    source_line: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct BankAcctFrom {
-    acctid: String,
+impl Display for Malformation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct CcAcctFrom {
-    acctid: String,
+impl Malformation {
+    /// The offending line, with a caret marking the column.
+    fn snippet(&self) -> String {
+        format!(
+            "{}\n{}^",
+            self.source_line,
+            " ".repeat(self.column.saturating_sub(1))
+        )
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct BankTranList {
-    #[serde(rename = "stmttrn")]
-    stmttrns: Vec<StmtTrn>,
+/// OFX1 content with common malformations repaired, and a record of what was repaired.
+///
+/// Unescaped ampersands and unknown entities are escaped, unpaired end tags are dropped, and
+/// any aggregates left open by a truncated file are closed.  Element structure is inferred in
+/// the manner of OFX, where an element followed by text is an unclosed leaf, and otherwise an
+/// aggregate.
+struct Repaired {
+    content: String,
+    malformations: Vec<Malformation>,
 }
 
-#[derive(Deserialize, Debug)]
-struct StmtTrn {
-    trntype: String,
-    dtposted: String,
-    trnamt: String,
-    fitid: String,
-    name: String,
-    memo: String,
+impl Repaired {
+    fn new(content: &str) -> Self {
+        let mut repairer = Repairer {
+            source: content,
+            repaired: String::with_capacity(content.len()),
+            malformations: Vec::default(),
+        };
+        repairer.repair();
+        Repaired {
+            content: repairer.repaired,
+            malformations: repairer.malformations,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct LedgerBal {
-    balamt: String,
-    dtasof: String,
+struct Repairer<'a> {
+    source: &'a str,
+    repaired: String,
+    malformations: Vec<Malformation>,
 }
 
-impl From<StmtTrn> for HashMap<String, String> {
-    fn from(value: StmtTrn) -> Self {
-        [
-            (TRNTYPE, value.trntype),
-            (DTPOSTED, truncate_yyyymmdd(value.dtposted)),
-            (TRNAMT, value.trnamt),
-            (FITID, value.fitid),
-            (NAME, value.name),
-            (MEMO, value.memo),
-        ]
-        .into_iter()
-        .map(|(k, v)| (k.to_string(), v))
-        .collect::<HashMap<_, _>>()
+impl<'a> Repairer<'a> {
+    fn repair(&mut self) {
+        let source = self.source;
+        // open elements, lowercased, with whether each is known to be a leaf
+        let mut open: Vec<(String, bool)> = Vec::default();
+        let mut i = 0;
+
+        while let Some(j) = source[i..].find(['<', '&']).map(|j| i + j) {
+            self.text(&source[i..j], &mut open);
+
+            if source[j..].starts_with('&') {
+                i = j + 1;
+                match ENTITY_RE.find(&source[j..]) {
+                    Some(m)
+                        if m.as_str().starts_with("&#")
+                            || expand_entity(&m.as_str()[1..]).is_some() =>
+                    {
+                        self.repaired.push('&');
+                    }
+                    Some(m) => {
+                        self.malformation(j, format!("unknown entity {}", m.as_str()));
+                        self.repaired.push_str("&amp;");
+                    }
+                    None => {
+                        self.malformation(j, "unescaped &".to_string());
+                        self.repaired.push_str("&amp;");
+                    }
+                }
+                continue;
+            }
+
+            let Some(k) = source[j + 1..].find(['<', '>']).map(|k| j + 1 + k) else {
+                self.malformation(j, "unterminated tag at end of file".to_string());
+                i = source.len();
+                break;
+            };
+            if source[k..].starts_with('<') {
+                self.malformation(j, "unterminated tag".to_string());
+                self.repaired.push_str("&lt;");
+                i = j + 1;
+                continue;
+            }
+
+            let tag = &source[j + 1..k];
+            i = k + 1;
+            if tag.starts_with(['!', '?']) {
+                self.repaired.push_str(&source[j..i]);
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim().to_lowercase();
+                match open.iter().rposition(|(open_name, _)| *open_name == name) {
+                    Some(position) => {
+                        open.truncate(position);
+                        self.repaired.push_str(&source[j..i]);
+                    }
+                    None => {
+                        self.malformation(j, format!("unpaired end tag </{}>", name));
+                    }
+                }
+            } else {
+                // an element followed by another tag is an aggregate, so leaves are no longer open
+                while open.last().is_some_and(|(_, is_leaf)| *is_leaf) {
+                    open.pop();
+                }
+                let name = tag.split_whitespace().next().unwrap_or_default();
+                open.push((name.to_lowercase(), false));
+                self.repaired.push_str(&source[j..i]);
+            }
+        }
+        self.text(&source[i..], &mut open);
+
+        let unclosed = open
+            .into_iter()
+            .filter_map(|(name, is_leaf)| (!is_leaf).then_some(name))
+            .collect::<Vec<_>>();
+        if !unclosed.is_empty() {
+            self.malformation(
+                source.len(),
+                format!("unexpected end of file, closing {}", unclosed.join(", ")),
+            );
+            for name in unclosed.iter().rev() {
+                self.repaired.push_str(&format!("</{}>", name));
+            }
+        }
     }
-}
 
-pub(crate) fn parse(
-    path: &Path,
-    ofx_content: &str,
-    ofxheader: &str,
-    version: &str,
-) -> Result<Hulls> {
-    let sgml = sgmlish::Parser::builder()
-        .lowercase_names()
-        .expand_entities(|entity| match entity {
-            "lt" => Some("<"),
-            "gt" => Some(">"),
-            "amp" => Some("&"),
-            "nbsp" => Some(" "),
-            _ => None,
-        })
-        .parse(ofx_content)
-        .wrap_err_with(|| format!("Failed to parse OFX1 in {}", path.to_string_lossy()))?;
-    let sgml = sgmlish::transforms::normalize_end_tags(sgml).wrap_err_with(|| {
-        format!(
-            "Failed to normalize OFX1 end tags in {}",
-            path.to_string_lossy()
-        )
-    })?;
-    let doc = sgmlish::from_fragment::<Document>(sgml)
-        .wrap_err_with(|| format!("Failed to deserialize OFX1 in {}", path.to_string_lossy()))?;
-
-    let hull = match doc {
-        Document {
-            bankmsgsrsv1:
-                Some(BankMsgsRsV1 {
-                    stmttrnrs:
-                        StmtTrnRs {
-                            stmtrs:
-                                StmtRs {
-                                    curdef,
-                                    bankacctfrom: BankAcctFrom { acctid },
-                                    banktranlist: BankTranList { stmttrns },
-                                    ledgerbal: LedgerBal { balamt, dtasof },
-                                },
-                        },
-                }),
-            creditcardmsgsrsv1: None,
-        } => Ok((curdef, acctid, balamt, dtasof, stmttrns)),
-
-        Document {
-            bankmsgsrsv1: None,
-            creditcardmsgsrsv1:
-                Some(CreditCardMsgsRsV1 {
-                    ccstmttrnrs:
-                        CcStmtTrnRs {
-                            ccstmtrs:
-                                CcStmtRs {
-                                    curdef,
-                                    ccacctfrom: CcAcctFrom { acctid },
-                                    banktranlist: BankTranList { stmttrns },
-                                    ledgerbal: LedgerBal { balamt, dtasof },
-                                },
-                        },
-                }),
-        } => Ok((curdef, acctid, balamt, dtasof, stmttrns)),
-
-        _ => Err(eyre!("unsupported OFX1 document {:?}", path)),
+    /// Copy text, which marks the most recently opened element as a leaf.
+    fn text(&mut self, text: &str, open: &mut [(String, bool)]) {
+        if !text.trim().is_empty()
+            && let Some((_, is_leaf)) = open.last_mut()
+        {
+            *is_leaf = true;
+        }
+        self.repaired.push_str(text);
     }
-    .map(|(curdef, acctid, balamt, dtasof, stmttrns)| Hull {
-        hdr: [
-            (OFXHEADER, ofxheader.to_string()),
-            (VERSION, version.to_string()),
-            (CURDEF, curdef),
-            (ACCTID, acctid),
-            (BALAMT, balamt),
-            (DTASOF, truncate_yyyymmdd(dtasof)),
-        ]
-        .into_iter()
-        .map(|(k, v)| (k.to_string(), v))
-        .collect::<HashMap<_, _>>(),
-        txns: stmttrns
-            .into_iter()
-            .map(Into::<HashMap<_, _>>::into)
-            .collect::<Vec<_>>(),
-    })?;
 
-    Ok(Hulls(vec![hull]))
+    fn malformation(&mut self, offset: usize, message: String) {
+        let line_start = self.source[..offset].rfind('\n').map_or(0, |n| n + 1);
+        let line_end = self.source[offset..]
+            .find(['\r', '\n'])
+            .map_or(self.source.len(), |n| offset + n);
+        self.malformations.push(Malformation {
+            line: 1 + self.source[..offset].matches('\n').count(),
+            column: self.source[line_start..offset].chars().count() + 1,
+            message,
+            source_line: self.source[line_start..line_end].to_string(),
+        });
+    }
 }
@@ -0,0 +1,298 @@
+use color_eyre::eyre::{Result, eyre};
+use std::{collections::HashMap, path::Path, str::FromStr};
+use time::{Date, Month};
+
+use crate::hull::{Hull, Hulls};
+use crate::year::full_year;
+
+/// Suffix for the key of the original date string
+const RAW_SUFFIX: &str = "-raw";
+
+const ACCOUNT_PREFIX: &str = "account.";
+const ADDRESS: &str = "address";
+const AMOUNT: &str = "amount";
+const DATE: &str = "date";
+const TYPE: &str = "type";
+
+/// Section types containing transactions, each of which is hulled separately.
+const TRANSACTION_TYPES: &[&str] = &["bank", "cash", "ccard", "invst", "oth a", "oth l"];
+
+/// Fields of an account, by QIF code.
+const ACCOUNT_FIELDS: &[(char, &str)] = &[
+    ('N', "name"),
+    ('T', "type"),
+    ('D', "description"),
+    ('L', "credit-limit"),
+    ('/', "balance-date"),
+    ('$', "balance"),
+];
+
+/// Fields of a bank, cash, credit card, or asset/liability transaction, by QIF code.
+///
+/// Newer versions of Quicken write the amount as both `T` and `U`.
+const TRANSACTION_FIELDS: &[(char, &str)] = &[
+    ('D', DATE),
+    ('T', AMOUNT),
+    ('U', AMOUNT),
+    ('C', "cleared"),
+    ('N', "number"),
+    ('P', "payee"),
+    ('M', "memo"),
+    ('A', ADDRESS),
+    ('L', "category"),
+];
+
+/// Fields of a split, by QIF code, which are keyed as `split.<n>.<field>` from 1.
+const SPLIT_FIELDS: &[(char, &str)] = &[
+    ('S', "category"),
+    ('E', "memo"),
+    ('$', AMOUNT),
+    ('%', "percent"),
+];
+
+/// Fields of an investment transaction, by QIF code.
+const INVESTMENT_FIELDS: &[(char, &str)] = &[
+    ('D', DATE),
+    ('N', "action"),
+    ('Y', "security"),
+    ('I', "price"),
+    ('Q', "quantity"),
+    ('T', AMOUNT),
+    ('U', AMOUNT),
+    ('C', "cleared"),
+    ('P', "payee"),
+    ('M', "memo"),
+    ('O', "commission"),
+    ('L', "category"),
+    ('$', "transfer-amount"),
+];
+
+/// Fields which are amounts, from which thousands separators are removed.
+const AMOUNT_FIELDS: &[&str] = &[
+    AMOUNT,
+    "balance",
+    "commission",
+    "credit-limit",
+    "price",
+    "quantity",
+    "transfer-amount",
+];
+
+/// Fields which are dates, normalised to `yyyyMMdd` with the original as `<field>-raw`.
+const DATE_FIELDS: &[&str] = &[DATE, "balance-date"];
+
+/// Order of day, month, and year in QIF dates, which the format leaves unspecified.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DateOrder {
+    Mdy,
+    Dmy,
+    Ymd,
+}
+
+impl FromStr for DateOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mdy" => Ok(DateOrder::Mdy),
+            "dmy" => Ok(DateOrder::Dmy),
+            "ymd" => Ok(DateOrder::Ymd),
+            _ => Err(format!(
+                "invalid date order {}, expected mdy, dmy, or ymd",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    /// Within an `!Account` block or list
+    Account,
+    /// Within a transaction section, whose type is the given one, lowercased
+    Transactions(String),
+    /// Within a section of no interest, such as categories or memorized transactions
+    Skip,
+}
+
+/// Hull each transaction section, with the fields of the preceding `!Account` block, if any, in the header.
+pub(crate) fn parse(path: &Path, content: &str, date_order: DateOrder) -> Result<Hulls> {
+    let mut hulls = Vec::<Hull>::default();
+    let mut mode = Mode::Skip;
+    let mut account = HashMap::<String, String>::default();
+    let mut record = HashMap::<String, String>::default();
+    let mut n_splits = 0;
+
+    for (i, line) in content.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim_end();
+        let invalid_date = |value: &str| {
+            eyre!(
+                "invalid date {} at line {} in {}, try --date-order",
+                value,
+                line_number,
+                path.to_string_lossy()
+            )
+        };
+
+        if let Some(header) = line.strip_prefix('!') {
+            // a record not terminated before the next header is taken as complete
+            if !record.is_empty() {
+                end_record(&mode, &mut record, &mut account, &mut hulls);
+            }
+            n_splits = 0;
+
+            let header = header.trim().to_lowercase();
+            if header == "account" {
+                mode = Mode::Account;
+            } else if let Some(section_type) = header.strip_prefix("type:") {
+                let section_type = section_type.trim();
+                if TRANSACTION_TYPES.contains(&section_type) {
+                    // the account applies only to the section it precedes
+                    let mut hdr = std::mem::take(&mut account)
+                        .into_iter()
+                        .map(|(k, v)| (format!("{}{}", ACCOUNT_PREFIX, k), v))
+                        .collect::<HashMap<_, _>>();
+                    hdr.insert(TYPE.to_string(), section_type.to_string());
+                    hulls.push(Hull {
+                        hdr,
+                        txns: Vec::default(),
+                        positions: Vec::default(),
+                    });
+                    mode = Mode::Transactions(section_type.to_string());
+                } else {
+                    mode = Mode::Skip;
+                }
+            }
+            // options such as !Option:AutoSwitch merely affect how accounts are listed
+        } else if line.starts_with('^') {
+            end_record(&mode, &mut record, &mut account, &mut hulls);
+            n_splits = 0;
+        } else if let Some(code) = line.chars().next() {
+            let value = line[code.len_utf8()..].trim();
+            let fields = match &mode {
+                Mode::Account => ACCOUNT_FIELDS,
+                Mode::Transactions(section_type) if section_type == "invst" => INVESTMENT_FIELDS,
+                Mode::Transactions(_) => TRANSACTION_FIELDS,
+                Mode::Skip => continue,
+            };
+
+            let key = match fields.iter().find(|(c, _)| *c == code) {
+                Some((_, key)) => key.to_string(),
+                None if fields == TRANSACTION_FIELDS => {
+                    match SPLIT_FIELDS.iter().find(|(c, _)| *c == code) {
+                        Some((c, key)) => {
+                            // each split starts with its category, or if that is omitted, its amount
+                            if *c == 'S'
+                                || n_splits == 0
+                                || record.contains_key(&split_key(n_splits, key))
+                            {
+                                n_splits += 1;
+                            }
+                            split_key(n_splits, key)
+                        }
+                        None => code.to_lowercase().to_string(),
+                    }
+                }
+                None => code.to_lowercase().to_string(),
+            };
+            let field_name = key.rsplit('.').next().unwrap_or(&key);
+
+            if DATE_FIELDS.contains(&field_name) {
+                let date = parse_date(value, date_order).ok_or_else(|| invalid_date(value))?;
+                record.insert(format!("{}{}", key, RAW_SUFFIX), value.to_string());
+                record.insert(key, yyyymmdd(date));
+            } else if AMOUNT_FIELDS.contains(&field_name) {
+                record.insert(key, value.replace(',', ""));
+            } else if field_name == ADDRESS {
+                // the address has multiple lines
+                record
+                    .entry(key)
+                    .and_modify(|address| {
+                        address.push('\n');
+                        address.push_str(value);
+                    })
+                    .or_insert_with(|| value.to_string());
+            } else {
+                record.insert(key, value.to_string());
+            }
+        }
+    }
+
+    if !record.is_empty() {
+        end_record(&mode, &mut record, &mut account, &mut hulls);
+    }
+
+    if hulls.is_empty() {
+        return Err(eyre!(
+            "no transaction sections found in QIF file {:?}",
+            path
+        ));
+    }
+
+    Ok(Hulls(hulls))
+}
+
+fn split_key(n: usize, key: &str) -> String {
+    format!("split.{}.{}", n, key)
+}
+
+/// Complete the record, as an account or transaction according to the mode.
+fn end_record(
+    mode: &Mode,
+    record: &mut HashMap<String, String>,
+    account: &mut HashMap<String, String>,
+    hulls: &mut [Hull],
+) {
+    let record = std::mem::take(record);
+    match mode {
+        Mode::Account => *account = record,
+        Mode::Transactions(_) => {
+            if let Some(hull) = hulls.last_mut() {
+                hull.txns.push(record);
+            }
+        }
+        Mode::Skip => (),
+    }
+}
+
+/// Parse a QIF date such as `1/ 2/98`, `1/ 2'05`, `01/02/2005`, `2005-01-02`, or `02.01.2005`.
+///
+/// Two digit years are taken as 20yy if written with an apostrophe, as Quicken does, or less
+/// than 70, and otherwise 19yy.  A date starting with a four digit year is always year first.
+fn parse_date(s: &str, date_order: DateOrder) -> Option<Date> {
+    let parts = s
+        .split(['/', '-', '.', '\'', ' '])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+    let [first, second, third] = parts.as_slice() else {
+        return None;
+    };
+
+    let (year, month, day) = if first.len() == 4 {
+        (*first, *second, *third)
+    } else {
+        match date_order {
+            DateOrder::Mdy => (*third, *first, *second),
+            DateOrder::Dmy => (*third, *second, *first),
+            DateOrder::Ymd => (*first, *second, *third),
+        }
+    };
+
+    let year = match (year.len(), year.parse::<i32>().ok()?) {
+        (1 | 2, year) if s.contains('\'') => 2000 + year,
+        (1 | 2, year) => full_year(year),
+        (_, year) => year,
+    };
+    let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    Date::from_calendar_date(year, month, day.parse::<u8>().ok()?).ok()
+}
+
+fn yyyymmdd(date: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
@@ -0,0 +1,16 @@
+use encoding_rs::Encoding;
+use regex::bytes::Regex;
+use std::{borrow::Cow, sync::LazyLock};
+
+use crate::decode::decode;
+
+static XML_ENCODING_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<\?xml[^>]*\bencoding="([^"]*)""#).unwrap());
+
+/// Decode XML content in the encoding declared by its XML declaration, if any.
+pub(crate) fn decode_xml(content: &[u8]) -> Cow<'_, str> {
+    let encoding = XML_ENCODING_RE
+        .captures(content)
+        .and_then(|captures| Encoding::for_label(captures.get(1).unwrap().as_bytes()));
+    decode(content, encoding)
+}
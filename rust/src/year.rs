@@ -0,0 +1,7 @@
+/// Two-digit years less than this are taken as 20yy, and otherwise as 19yy.
+const PIVOT: i32 = 70;
+
+/// Expand a two-digit year into a full year, e.g. 25 as 2025 and 98 as 1998.
+pub(crate) fn full_year(yy: i32) -> i32 {
+    if yy < PIVOT { 2000 + yy } else { 1900 + yy }
+}